@@ -1,4 +1,31 @@
+use std::ops::Index;
+
+/// How a color is combined with the color beneath it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The top color replaces the base color.
+    Normal,
+    /// Channels are multiplied, darkening the result.
+    Multiply,
+    /// Inverted channels are multiplied, lightening the result.
+    Screen,
+    /// Channels are summed and saturate at 255.
+    Add,
+    /// Multiply for dark base channels, screen for light ones.
+    Overlay,
+}
+
+/// A single channel of a `Color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    A,
+}
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -6,6 +33,21 @@ pub struct Color {
     pub a: u8,
 }
 
+/// Channels in `r`, `g`, `b`, `a` order, so `color[3]` is alpha.
+impl Index<usize> for Color {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &u8 {
+        match index {
+            0 => &self.r,
+            1 => &self.g,
+            2 => &self.b,
+            3 => &self.a,
+            _ => panic!("color channel index {index} out of range"),
+        }
+    }
+}
+
 impl Color {
     /// Blend two colors based on a given factor.
     ///
@@ -27,6 +69,45 @@ impl Color {
         }
     }
 
+    /// Blend two colors in premultiplied alpha space.
+    ///
+    /// Unlike [`Color::blend`], a transparent color contributes no RGB, so
+    /// blending toward it fades the alpha without darkening toward its
+    /// (invisible) channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The color to blend with.
+    /// * `factor` - The blend factor (0.0 to 1.0), weighting `self` the same way as [`Color::blend`].
+    ///
+    /// # Returns
+    ///
+    /// A new straight-alpha `Color` resulting from the blend. Blending two
+    /// fully transparent colors gives transparent black.
+    pub fn blend_premultiplied(&self, other: Color, factor: f64) -> Color {
+        let factor = factor.clamp(0.0, 1.0);
+        let self_weight = self.opacity() * factor;
+        let other_weight = other.opacity() * (1.0 - factor);
+        let alpha = self_weight + other_weight;
+        if alpha <= 0.0 {
+            return Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+        }
+        let mix = |a: u8, b: u8| {
+            ((a as f64 * self_weight + b as f64 * other_weight) / alpha).round() as u8
+        };
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: (alpha * 255.0).round() as u8,
+        }
+    }
+
     /// Adjust brightness of the color.
     ///
     /// # Arguments
@@ -45,6 +126,25 @@ impl Color {
         }
     }
 
+    /// Scale the brightness of the color by a factor.
+    ///
+    /// # Arguments
+    ///
+    /// * `factor` - The multiplier for each channel, e.g. 0.8 darkens by 20% and 1.2 lightens by 20%.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` with scaled and clamped RGB channels and the same alpha.
+    pub fn scale_brightness(&self, factor: f64) -> Color {
+        let scale = |channel: u8| (channel as f64 * factor).round().clamp(0.0, 255.0) as u8;
+        Color {
+            r: scale(self.r),
+            g: scale(self.g),
+            b: scale(self.b),
+            a: self.a,
+        }
+    }
+
     /// Convert to grayscale.
     ///
     /// # Returns
@@ -60,6 +160,27 @@ impl Color {
         }
     }
 
+    /// Convert to grayscale, weighting the channels by alpha first.
+    ///
+    /// Fully transparent colors come out as transparent black instead of
+    /// keeping a gray derived from color that would never be visible.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` whose gray level is premultiplied by its alpha, with the
+    /// original alpha preserved.
+    pub fn grayscale_premultiplied(&self) -> Color {
+        let alpha = self.opacity();
+        let gray =
+            ((self.r as f64 * 0.299 + self.g as f64 * 0.587 + self.b as f64 * 0.114) * alpha) as u8;
+        Color {
+            r: gray,
+            g: gray,
+            b: gray,
+            a: self.a,
+        }
+    }
+
     /// Create a new color with the specified alpha value.
     ///
     /// # Arguments
@@ -78,6 +199,42 @@ impl Color {
         }
     }
 
+    /// Create a new color with the specified RGB values, keeping this color's alpha.
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - The red value (0-255).
+    /// * `g` - The green value (0-255).
+    /// * `b` - The blue value (0-255).
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` with the given RGB values and the alpha of `self`.
+    pub fn with_rgb(&self, r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: self.a }
+    }
+
+    /// Create a new color with a single channel replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `ch` - The channel to replace.
+    /// * `value` - The new value (0-255) for that channel.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` with the channel updated and the others unchanged.
+    pub fn with_channel(&self, ch: Channel, value: u8) -> Color {
+        let mut color = *self;
+        match ch {
+            Channel::R => color.r = value,
+            Channel::G => color.g = value,
+            Channel::B => color.b = value,
+            Channel::A => color.a = value,
+        }
+        color
+    }
+
     /// Interpolate between this color and another color.
     ///
     /// # Arguments
@@ -97,6 +254,45 @@ impl Color {
         }
     }
 
+    /// Interpolate across a multi-stop gradient.
+    ///
+    /// # Arguments
+    ///
+    /// * `stops` - `(position, color)` pairs sorted by ascending position.
+    /// * `t` - The position to sample, clamped to the first and last stops.
+    ///
+    /// # Returns
+    ///
+    /// The color interpolated between the two stops bracketing `t`, or
+    /// transparent black if `stops` is empty.
+    pub fn gradient(stops: &[(f64, Color)], t: f64) -> Color {
+        let (Some(&(first_pos, first)), Some(&(last_pos, last))) = (stops.first(), stops.last())
+        else {
+            return Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+        };
+        if t <= first_pos {
+            return first;
+        }
+        if t >= last_pos {
+            return last;
+        }
+        let upper = stops
+            .iter()
+            .position(|&(pos, _)| pos >= t)
+            .unwrap_or(stops.len() - 1);
+        let (start_pos, start) = stops[upper - 1];
+        let (end_pos, end) = stops[upper];
+        if end_pos <= start_pos {
+            return end;
+        }
+        start.interpolate(end, (t - start_pos) / (end_pos - start_pos))
+    }
+
     /// Invert the color.
     ///
     /// # Returns
@@ -111,6 +307,43 @@ impl Color {
         }
     }
 
+    /// Create a color approximating a blackbody radiator at a given temperature.
+    ///
+    /// # Arguments
+    ///
+    /// * `kelvin` - The color temperature, clamped to 1000K to 40000K.
+    ///
+    /// # Returns
+    ///
+    /// A new opaque `Color`, e.g. orange for 2000K and near-white for 6500K.
+    pub fn from_kelvin(kelvin: f64) -> Color {
+        // Tanner Helland's curve fit of the blackbody spectrum.
+        let temp = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if temp <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (temp - 60.0).powf(-0.1332047592)
+        };
+        let g = if temp <= 66.0 {
+            99.4708025861 * temp.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (temp - 60.0).powf(-0.0755148492)
+        };
+        let b = if temp >= 66.0 {
+            255.0
+        } else if temp <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (temp - 10.0).ln() - 305.0447927307
+        };
+        Color {
+            r: r.clamp(0.0, 255.0) as u8,
+            g: g.clamp(0.0, 255.0) as u8,
+            b: b.clamp(0.0, 255.0) as u8,
+            a: 255,
+        }
+    }
+
     /// Convert the color to an RGBA tuple.
     ///
     /// # Returns
@@ -138,6 +371,44 @@ impl Color {
         }
     }
 
+    /// Premultiply the color channels by alpha.
+    ///
+    /// # Returns
+    ///
+    /// A `PremulColor` holding this color's premultiplied channels.
+    pub fn premultiply(&self) -> PremulColor {
+        let scale = |channel: u8| (channel as f64 * self.opacity()).round() as u8;
+        PremulColor(Color {
+            r: scale(self.r),
+            g: scale(self.g),
+            b: scale(self.b),
+            a: self.a,
+        })
+    }
+
+    /// Get the channels as an array.
+    ///
+    /// # Returns
+    ///
+    /// An array `[r, g, b, a]`, indexed the same way as `color[i]`.
+    pub fn channels(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Create a color from an array of channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - An array containing `[r, g, b, a]`.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` created from the provided channels.
+    pub fn from_channels(channels: [u8; 4]) -> Color {
+        let [r, g, b, a] = channels;
+        Color { r, g, b, a }
+    }
+
     /// Adjust the alpha value of the color.
     ///
     /// # Arguments
@@ -156,4 +427,599 @@ impl Color {
             a: new_alpha,
         }
     }
+
+    /// Check whether the color is fully opaque.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the alpha value is 255, otherwise `false`.
+    pub fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    /// Check whether the color is fully transparent.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the alpha value is 0, otherwise `false`.
+    pub fn is_transparent(&self) -> bool {
+        self.a == 0
+    }
+
+    /// Get the opacity of the color.
+    ///
+    /// # Returns
+    ///
+    /// The alpha value normalized to the range 0.0 to 1.0.
+    pub fn opacity(&self) -> f64 {
+        self.a as f64 / 255.0
+    }
+
+    /// Blend another color on top of this one using a blend mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The color layered on top of this one.
+    /// * `mode` - The `BlendMode` used to combine the channels.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` with the blended channels and the alpha of `self`.
+    pub fn blend_with_mode(&self, other: Color, mode: BlendMode) -> Color {
+        let channel = |base: u8, top: u8| -> u8 {
+            let a = base as f64 / 255.0;
+            let b = top as f64 / 255.0;
+            let result = match mode {
+                BlendMode::Normal => b,
+                BlendMode::Multiply => a * b,
+                BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+                BlendMode::Add => a + b,
+                BlendMode::Overlay => {
+                    if a < 0.5 {
+                        2.0 * a * b
+                    } else {
+                        1.0 - 2.0 * (1.0 - a) * (1.0 - b)
+                    }
+                }
+            };
+            (result.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        Color {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: self.a,
+        }
+    }
+
+    /// Calculate the WCAG contrast ratio between this color and another color.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The color to compare against.
+    ///
+    /// # Returns
+    ///
+    /// The contrast ratio, from 1.0 for identical colors up to 21.0 for black
+    /// against white. Alpha is ignored.
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    fn relative_luminance(&self) -> f64 {
+        let linear = |channel: u8| {
+            let c = channel as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Check whether two colors are equal within a per-channel tolerance.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The color to compare with.
+    /// * `tolerance` - The largest allowed difference in any channel, including alpha.
+    ///
+    /// # Returns
+    ///
+    /// `true` if every channel differs by at most `tolerance`, otherwise `false`.
+    pub fn approx_eq(&self, other: &Color, tolerance: u8) -> bool {
+        self.r.abs_diff(other.r) <= tolerance
+            && self.g.abs_diff(other.g) <= tolerance
+            && self.b.abs_diff(other.b) <= tolerance
+            && self.a.abs_diff(other.a) <= tolerance
+    }
+
+    /// Get a key for sorting colors deterministically.
+    ///
+    /// # Returns
+    ///
+    /// The color packed as `0xRRGGBBAA`, so colors order by red, then green,
+    /// blue and alpha.
+    pub fn sort_key(&self) -> u32 {
+        u32::from_be_bytes([self.r, self.g, self.b, self.a])
+    }
+
+    /// Convert the color to CMYK using the standard (non-ICC) formula.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(c, m, y, k)` with each component in the range 0.0 to 1.0. Alpha is ignored.
+    pub fn to_cmyk(&self) -> (f64, f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+        let k = 1.0 - r.max(g).max(b);
+        if k >= 1.0 {
+            return (0.0, 0.0, 0.0, 1.0);
+        }
+        (
+            (1.0 - r - k) / (1.0 - k),
+            (1.0 - g - k) / (1.0 - k),
+            (1.0 - b - k) / (1.0 - k),
+            k,
+        )
+    }
+
+    /// Create a color from CMYK components using the standard (non-ICC) formula.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The cyan component (0.0 to 1.0).
+    /// * `m` - The magenta component (0.0 to 1.0).
+    /// * `y` - The yellow component (0.0 to 1.0).
+    /// * `k` - The key (black) component (0.0 to 1.0).
+    /// * `a` - The alpha value (0-255).
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` created from the provided CMYK values.
+    pub fn from_cmyk(c: f64, m: f64, y: f64, k: f64, a: u8) -> Color {
+        let channel =
+            |v: f64| (255.0 * (1.0 - v.clamp(0.0, 1.0)) * (1.0 - k.clamp(0.0, 1.0))).round() as u8;
+        Color {
+            r: channel(c),
+            g: channel(m),
+            b: channel(y),
+            a,
+        }
+    }
+
+    /// Convert the color to HSL.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(h, s, l)` with hue in degrees (0.0 to 360.0) and saturation and
+    /// lightness in the range 0.0 to 1.0. Grays have a hue and saturation of 0.0.
+    /// Alpha is ignored.
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (h, s, l)
+    }
+
+    /// Create a color from HSL components.
+    ///
+    /// # Arguments
+    ///
+    /// * `h` - The hue in degrees. Values outside 0.0 to 360.0 wrap around.
+    /// * `s` - The saturation (0.0 to 1.0).
+    /// * `l` - The lightness (0.0 to 1.0).
+    /// * `a` - The alpha value (0-255).
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` created from the provided HSL values.
+    pub fn from_hsl(h: f64, s: f64, l: f64, a: u8) -> Color {
+        let h = h.rem_euclid(360.0);
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = chroma * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = l - chroma / 2.0;
+        let (r, g, b) = match (h / 60.0) as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Color {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+            a,
+        }
+    }
+
+    /// Find the closest color in a palette.
+    ///
+    /// # Arguments
+    ///
+    /// * `palette` - The colors to choose from.
+    ///
+    /// # Returns
+    ///
+    /// The palette entry with the smallest Euclidean RGB distance to this color,
+    /// or this color unchanged if the palette is empty.
+    pub fn nearest_in_palette(&self, palette: &[Color]) -> Color {
+        let distance = |other: &Color| {
+            let dr = self.r as i32 - other.r as i32;
+            let dg = self.g as i32 - other.g as i32;
+            let db = self.b as i32 - other.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+        palette
+            .iter()
+            .min_by_key(|color| distance(color))
+            .copied()
+            .unwrap_or(*self)
+    }
+}
+
+/// A color whose RGB channels are already multiplied by its alpha.
+///
+/// Keeping premultiplied colors in their own type stops them from being
+/// mixed up with straight-alpha `Color`s when compositing.
+#[derive(Debug, Clone, Copy)]
+pub struct PremulColor(Color);
+
+impl PremulColor {
+    /// Get the premultiplied channels.
+    ///
+    /// # Returns
+    ///
+    /// The channels as stored, with RGB already scaled by alpha.
+    pub fn color(&self) -> Color {
+        self.0
+    }
+
+    /// Convert back to straight alpha.
+    ///
+    /// # Returns
+    ///
+    /// A new `Color` with RGB divided by alpha, or transparent black if the
+    /// color is fully transparent.
+    pub fn unpremultiply(&self) -> Color {
+        let Color { r, g, b, a } = self.0;
+        if a == 0 {
+            return Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+        }
+        let scale = |channel: u8| (channel as f64 * 255.0 / a as f64).round().min(255.0) as u8;
+        Color {
+            r: scale(r),
+            g: scale(g),
+            b: scale(b),
+            a,
+        }
+    }
+
+    /// Composite this color over another with the Porter-Duff "over" operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `dst` - The premultiplied color underneath this one.
+    ///
+    /// # Returns
+    ///
+    /// The premultiplied result of layering this color on top of `dst`.
+    pub fn over(&self, dst: PremulColor) -> PremulColor {
+        let remaining = 1.0 - self.0.opacity();
+        let mix = |src: u8, dst: u8| (src as f64 + dst as f64 * remaining).round().min(255.0) as u8;
+        PremulColor(Color {
+            r: mix(self.0.r, dst.0.r),
+            g: mix(self.0.g, dst.0.g),
+            b: mix(self.0.b, dst.0.b),
+            a: mix(self.0.a, dst.0.a),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn opacity_predicates_follow_alpha() {
+        let opaque = rgba(10, 20, 30, 255);
+        let clear = opaque.with_alpha(0);
+        assert!(opaque.is_opaque() && !opaque.is_transparent());
+        assert!(clear.is_transparent() && !clear.is_opaque());
+        assert_eq!(opaque.opacity(), 1.0);
+        assert_eq!(clear.opacity(), 0.0);
+        assert!((opaque.with_alpha(51).opacity() - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn blend_modes_combine_channels_and_keep_base_alpha() {
+        let base = rgba(255, 128, 0, 200);
+        let top = rgba(128, 128, 255, 255);
+        let blend = |mode| base.blend_with_mode(top, mode).to_rgba();
+        assert_eq!(blend(BlendMode::Normal), (128, 128, 255, 200));
+        assert_eq!(blend(BlendMode::Multiply), (128, 64, 0, 200));
+        assert_eq!(blend(BlendMode::Screen), (255, 192, 255, 200));
+        assert_eq!(blend(BlendMode::Add), (255, 255, 255, 200));
+        // Add sums each channel and clamps only the ones that overflow.
+        let sum = rgba(10, 100, 200, 50).blend_with_mode(rgba(20, 100, 100, 255), BlendMode::Add);
+        assert_eq!(sum.to_rgba(), (30, 200, 255, 50));
+        let overlay =
+            rgba(0, 255, 64, 255).blend_with_mode(rgba(200, 200, 128, 255), BlendMode::Overlay);
+        assert_eq!(overlay.to_rgba(), (0, 255, 64, 255));
+    }
+
+    #[test]
+    fn contrast_ratio_spans_one_to_twenty_one() {
+        let black = rgba(0, 0, 0, 255);
+        let white = rgba(255, 255, 255, 255);
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 1e-9);
+        assert!((white.contrast_ratio(&black) - 21.0).abs() < 1e-9);
+        assert_eq!(white.contrast_ratio(&white.with_alpha(0)), 1.0);
+        let gray = rgba(119, 119, 119, 255);
+        assert!((gray.contrast_ratio(&white) - 4.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn with_rgb_and_with_channel_keep_the_other_channels() {
+        let color = rgba(1, 2, 3, 77);
+        assert_eq!(color.with_rgb(9, 8, 7).to_rgba(), (9, 8, 7, 77));
+        assert_eq!(color.with_channel(Channel::R, 50).to_rgba(), (50, 2, 3, 77));
+        assert_eq!(color.with_channel(Channel::G, 50).to_rgba(), (1, 50, 3, 77));
+        assert_eq!(color.with_channel(Channel::B, 50).to_rgba(), (1, 2, 50, 77));
+        assert_eq!(color.with_channel(Channel::A, 50).to_rgba(), (1, 2, 3, 50));
+    }
+
+    #[test]
+    fn scale_brightness_is_relative_and_clamped() {
+        let color = rgba(100, 200, 50, 9);
+        assert_eq!(color.scale_brightness(0.8).to_rgba(), (80, 160, 40, 9));
+        assert_eq!(color.scale_brightness(1.2).to_rgba(), (120, 240, 60, 9));
+        assert_eq!(color.scale_brightness(2.0).to_rgba(), (200, 255, 100, 9));
+        assert_eq!(color.scale_brightness(-1.0).to_rgba(), (0, 0, 0, 9));
+    }
+
+    #[test]
+    fn from_kelvin_runs_from_warm_to_cool() {
+        let candle = Color::from_kelvin(2000.0);
+        let daylight = Color::from_kelvin(6500.0);
+        let sky = Color::from_kelvin(15000.0);
+        assert_eq!(candle.r, 255);
+        assert!(candle.b < candle.g && candle.g < candle.r);
+        assert!(daylight.approx_eq(&rgba(255, 255, 255, 255), 10));
+        assert!(sky.b > sky.r);
+        assert!(Color::from_kelvin(0.0).approx_eq(&Color::from_kelvin(1000.0), 0));
+        assert!(candle.is_opaque());
+    }
+
+    #[test]
+    fn approx_eq_checks_every_channel_against_the_tolerance() {
+        let color = rgba(100, 100, 100, 100);
+        assert!(color.approx_eq(&color, 0));
+        assert!(color.approx_eq(&rgba(103, 97, 100, 102), 3));
+        assert!(!color.approx_eq(&rgba(104, 100, 100, 100), 3));
+        assert!(!color.approx_eq(&rgba(100, 100, 100, 96), 3));
+    }
+
+    #[test]
+    fn cmyk_round_trips() {
+        assert_eq!(rgba(0, 0, 0, 255).to_cmyk(), (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(rgba(255, 0, 0, 255).to_cmyk(), (0.0, 1.0, 1.0, 0.0));
+        for color in [
+            rgba(255, 255, 255, 255),
+            rgba(12, 200, 99, 40),
+            rgba(1, 2, 3, 0),
+            rgba(250, 128, 7, 128),
+        ] {
+            let (c, m, y, k) = color.to_cmyk();
+            let back = Color::from_cmyk(c, m, y, k, color.a);
+            assert_eq!(back.to_rgba(), color.to_rgba());
+        }
+    }
+
+    #[test]
+    fn nearest_in_palette_picks_the_closest_entry() {
+        let palette = [
+            rgba(0, 0, 0, 255),
+            rgba(255, 0, 0, 255),
+            rgba(255, 255, 255, 255),
+        ];
+        assert_eq!(
+            rgba(200, 40, 30, 255)
+                .nearest_in_palette(&palette)
+                .to_rgba(),
+            (255, 0, 0, 255)
+        );
+        assert_eq!(
+            rgba(30, 30, 30, 255).nearest_in_palette(&palette).to_rgba(),
+            (0, 0, 0, 255)
+        );
+        assert_eq!(
+            rgba(5, 6, 7, 8).nearest_in_palette(&[]).to_rgba(),
+            (5, 6, 7, 8)
+        );
+    }
+
+    #[test]
+    fn grayscale_premultiplied_weights_by_alpha() {
+        let white = rgba(255, 255, 255, 255);
+        assert_eq!(
+            white.grayscale_premultiplied().to_rgba(),
+            white.grayscale().to_rgba()
+        );
+        assert_eq!(
+            white.with_alpha(0).grayscale_premultiplied().to_rgba(),
+            (0, 0, 0, 0)
+        );
+        let half = white.with_alpha(128).grayscale_premultiplied();
+        assert_eq!(half.a, 128);
+        assert!(half.approx_eq(&rgba(128, 128, 128, 128), 1));
+    }
+
+    #[test]
+    fn sort_key_orders_by_red_then_green_blue_and_alpha() {
+        let mut colors = [
+            rgba(1, 0, 0, 0),
+            rgba(0, 0, 0, 1),
+            rgba(0, 1, 0, 0),
+            rgba(0, 0, 1, 0),
+        ];
+        colors.sort_by_key(Color::sort_key);
+        let sorted: Vec<_> = colors.iter().map(Color::to_rgba).collect();
+        assert_eq!(
+            sorted,
+            [(0, 0, 0, 1), (0, 0, 1, 0), (0, 1, 0, 0), (1, 0, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn blend_premultiplied_ignores_the_rgb_of_transparent_colors() {
+        let red = rgba(255, 0, 0, 255);
+        let clear_black = rgba(0, 0, 0, 0);
+        assert_eq!(red.blend(clear_black, 0.5).to_rgba(), (127, 0, 0, 127));
+        assert_eq!(
+            red.blend_premultiplied(clear_black, 0.5).to_rgba(),
+            (255, 0, 0, 128)
+        );
+        assert_eq!(
+            clear_black.blend_premultiplied(clear_black, 0.5).to_rgba(),
+            (0, 0, 0, 0)
+        );
+        let blue = rgba(0, 0, 255, 255);
+        assert_eq!(red.blend_premultiplied(blue, 1.0).to_rgba(), red.to_rgba());
+    }
+
+    #[test]
+    fn gradient_interpolates_between_bracketing_stops() {
+        let stops = [
+            (0.0, rgba(0, 0, 0, 255)),
+            (0.5, rgba(200, 0, 0, 255)),
+            (1.0, rgba(200, 100, 0, 255)),
+        ];
+        assert_eq!(Color::gradient(&stops, -1.0).to_rgba(), (0, 0, 0, 255));
+        assert_eq!(Color::gradient(&stops, 0.25).to_rgba(), (100, 0, 0, 255));
+        assert_eq!(Color::gradient(&stops, 0.75).to_rgba(), (200, 50, 0, 255));
+        assert_eq!(Color::gradient(&stops, 2.0).to_rgba(), (200, 100, 0, 255));
+        assert_eq!(Color::gradient(&[], 0.5).to_rgba(), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn hsl_round_trips() {
+        assert_eq!(rgba(255, 0, 0, 255).to_hsl(), (0.0, 1.0, 0.5));
+        assert_eq!(rgba(128, 128, 128, 255).to_hsl().1, 0.0);
+        let (h, s, l) = rgba(0, 0, 255, 255).to_hsl();
+        assert!((h - 240.0).abs() < 1e-9 && s == 1.0 && l == 0.5);
+        for color in [
+            rgba(0, 0, 0, 255),
+            rgba(255, 255, 255, 0),
+            rgba(12, 200, 99, 40),
+            rgba(250, 128, 7, 128),
+            rgba(90, 10, 200, 255),
+        ] {
+            let (h, s, l) = color.to_hsl();
+            assert_eq!(Color::from_hsl(h, s, l, color.a).to_rgba(), color.to_rgba());
+        }
+        assert_eq!(
+            Color::from_hsl(-120.0, 1.0, 0.5, 255).to_rgba(),
+            (0, 0, 255, 255)
+        );
+    }
+
+    #[test]
+    fn channels_and_index_agree() {
+        let color = rgba(1, 2, 3, 4);
+        assert_eq!(color.channels(), [1, 2, 3, 4]);
+        assert_eq!(
+            Color::from_channels([1, 2, 3, 4]).to_rgba(),
+            color.to_rgba()
+        );
+        for (i, channel) in color.channels().into_iter().enumerate() {
+            assert_eq!(color[i], channel);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn index_past_alpha_panics() {
+        let _ = rgba(1, 2, 3, 4)[4];
+    }
+
+    #[test]
+    fn premultiplied_colors_composite_and_convert_back() {
+        let half_red = rgba(255, 0, 0, 128).premultiply();
+        assert_eq!(half_red.color().channels(), [128, 0, 0, 128]);
+        assert_eq!(half_red.unpremultiply().channels(), [255, 0, 0, 128]);
+        assert_eq!(
+            rgba(9, 9, 9, 0).premultiply().unpremultiply().channels(),
+            [0, 0, 0, 0]
+        );
+
+        let blue = rgba(0, 0, 255, 255).premultiply();
+        let over = half_red.over(blue);
+        assert_eq!(over.color().channels(), [128, 0, 127, 255]);
+        let opaque = rgba(10, 20, 30, 255).premultiply();
+        assert_eq!(opaque.over(blue).color().channels(), [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn premultiplied_mixing_matches_straight_blend() {
+        // Mixes premultiplied channels, weighting `a` by `t` like `Color::blend`.
+        let mix = |a: PremulColor, b: PremulColor, t: f64| {
+            let channel = |a: u8, b: u8| (a as f64 * t + b as f64 * (1.0 - t)).round() as u8;
+            let (a, b) = (a.color(), b.color());
+            PremulColor(Color {
+                r: channel(a.r, b.r),
+                g: channel(a.g, b.g),
+                b: channel(a.b, b.b),
+                a: channel(a.a, b.a),
+            })
+        };
+        let pairs = [
+            (rgba(255, 40, 0, 255), rgba(0, 90, 200, 255)),
+            (rgba(255, 40, 0, 160), rgba(0, 90, 200, 160)),
+            (rgba(255, 40, 0, 200), rgba(0, 90, 200, 60)),
+        ];
+        for (a, b) in pairs {
+            for t in [0.0, 0.25, 0.5, 0.9, 1.0] {
+                let mixed = mix(a.premultiply(), b.premultiply(), t).unpremultiply();
+                let expected = a.blend_premultiplied(b, t);
+                assert!(mixed.approx_eq(&expected, 2), "{mixed:?} vs {expected:?}");
+                if a.a == b.a {
+                    assert!(mixed.approx_eq(&a.blend(b, t), 2));
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::Point;
+
+/// Errors reported by fallible `Map` operations.
+#[derive(Debug)]
+pub enum MapError {
+    /// A buffer or grid does not match the size implied by the map dimensions.
+    DimensionMismatch {
+        what: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// A light is positioned outside the map.
+    LightOutOfBounds { index: usize, position: Point },
+    /// A light has settings that cannot be rendered.
+    InvalidLight { reason: &'static str },
+    /// A light index does not refer to a light in the map.
+    LightIndexOutOfRange { index: usize, count: usize },
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// An image file could not be decoded.
+    Decode(png::DecodingError),
+    /// An image could not be encoded.
+    Encode(png::EncodingError),
+    /// A scene file could not be serialized or parsed.
+    #[cfg(feature = "serde")]
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::DimensionMismatch {
+                what,
+                expected,
+                actual,
+            } => write!(f, "{what} has size {actual}, expected {expected}"),
+            MapError::LightOutOfBounds { index, position } => write!(
+                f,
+                "light {index} at ({}, {}) is outside the map",
+                position.x, position.y
+            ),
+            MapError::InvalidLight { reason } => write!(f, "invalid light: {reason}"),
+            MapError::LightIndexOutOfRange { index, count } => {
+                write!(f, "light index {index} is out of range for {count} lights")
+            }
+            MapError::Io(err) => write!(f, "i/o error: {err}"),
+            MapError::Decode(err) => write!(f, "image decode error: {err}"),
+            MapError::Encode(err) => write!(f, "image encode error: {err}"),
+            #[cfg(feature = "serde")]
+            MapError::Json(err) => write!(f, "scene format error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<std::io::Error> for MapError {
+    fn from(err: std::io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for MapError {
+    fn from(err: png::DecodingError) -> Self {
+        MapError::Decode(err)
+    }
+}
+
+impl From<png::EncodingError> for MapError {
+    fn from(err: png::EncodingError) -> Self {
+        MapError::Encode(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for MapError {
+    fn from(err: serde_json::Error) -> Self {
+        MapError::Json(err)
+    }
+}
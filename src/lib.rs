@@ -1,17 +1,238 @@
+use std::borrow::Cow;
 use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
 use std::vec;
 mod color;
+mod error;
 mod point;
-pub use color::Color;
-pub use point::Point;
+#[cfg(feature = "serde")]
+mod scene;
+pub use color::{BlendMode, Channel, Color, PremulColor};
+pub use error::MapError;
+pub use point::{Easing, Point};
 
-#[derive(Debug)]
+/// Decodes a PNG of any 8 or 16 bit color type into 8-bit RGBA, returning the
+/// pixel data along with its width and height.
+fn decode_png_rgba(source: impl std::io::Read) -> Result<(Vec<u8>, u32, u32), MapError> {
+    let mut decoder = png::Decoder::new(source);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    let rgba = data[..info.buffer_size()]
+        .chunks(info.color_type.samples())
+        .flat_map(|texel| match texel.len() {
+            1 => [texel[0], texel[0], texel[0], 0xff],
+            2 => [texel[0], texel[0], texel[0], texel[1]],
+            3 => [texel[0], texel[1], texel[2], 0xff],
+            _ => [texel[0], texel[1], texel[2], texel[3]],
+        })
+        .collect();
+    Ok((rgba, info.width, info.height))
+}
+
+fn write_png(path: &str, width: u32, height: u32, color: png::ColorType, data: &[u8]) {
+    try_write_png(path, width, height, color, data).unwrap();
+}
+
+fn try_write_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    data: &[u8],
+) -> Result<(), MapError> {
+    let mut encoder = png::Encoder::new(File::create(path)?, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Nearest-neighbour upscales a `width` x `height` image with `channels` bytes
+/// per pixel by an integer `scale`.
+fn upscale(data: &[u8], width: u64, height: u64, channels: u64, scale: u64) -> Vec<u8> {
+    let end_width = width * scale;
+    let mut out = vec![0; (end_width * height * scale * channels) as usize];
+    if out.is_empty() {
+        // Zero-sized images have no rows to split into.
+        return out;
+    }
+    for (y, row) in out.chunks_mut((end_width * channels) as usize).enumerate() {
+        let source_row = (y as u64 / scale * width * channels) as usize;
+        for (x, pixel) in row.chunks_mut(channels as usize).enumerate() {
+            let source_index = source_row + (x as u64 / scale * channels) as usize;
+            pixel.copy_from_slice(&data[source_index..source_index + channels as usize]);
+        }
+    }
+    out
+}
+
+/// Blends an RGBA `layer` over an RGB `buffer` of the same size by the
+/// layer's alpha.
+fn merge_layer(buffer: &mut [u8], layer: &[u8]) {
+    for (pixel, texel) in buffer.chunks_mut(3).zip(layer.chunks(4)) {
+        let color = Color {
+            r: pixel[0],
+            g: pixel[1],
+            b: pixel[2],
+            a: 0xff,
+        };
+        let other = Color {
+            r: texel[0],
+            g: texel[1],
+            b: texel[2],
+            a: texel[3],
+        };
+        let new_color = other.blend(color, other.opacity());
+        pixel.copy_from_slice(&[new_color.r, new_color.g, new_color.b]);
+    }
+}
+
+fn expose(data: &mut [u8], exposure: f64) {
+    for channel in data.iter_mut() {
+        let c = *channel as f64 / 255.0;
+        *channel = ((1.0 - (-c * exposure).exp()) * 255.0).round() as u8;
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Light {
     pub position: Point,
     pub color: Color,
     pub intensity: f64,
     pub angle: f64,
     pub fov: f64,
+    /// Disabled lights stay in the scene but are skipped when rendering.
+    pub enabled: bool,
+    /// Whether walls block this light. Lights that cast no shadows, such as
+    /// ambient fill, shine through walls to everything in reach.
+    pub casts_shadows: bool,
+    /// Inner and outer colors the light shifts between across its reach. When
+    /// set, it replaces `color`.
+    pub falloff_gradient: Option<(Color, Color)>,
+}
+
+impl Default for Light {
+    /// A white, enabled, shadow-casting light of intensity 1 at the origin,
+    /// shining in every direction.
+    fn default() -> Self {
+        Light {
+            position: Point::new(0.0, 0.0),
+            color: Color {
+                r: 0xff,
+                g: 0xff,
+                b: 0xff,
+                a: 0xff,
+            },
+            intensity: 1.0,
+            angle: 0.0,
+            fov: 360.0,
+            enabled: true,
+            casts_shadows: true,
+            falloff_gradient: None,
+        }
+    }
+}
+
+impl Light {
+    /// Creates an enabled, shadow-casting light with the remaining fields
+    /// taken from [`Light::default`].
+    pub fn new(position: Point, color: Color, intensity: f64) -> Light {
+        Light {
+            position,
+            color,
+            intensity,
+            ..Default::default()
+        }
+    }
+
+    /// The color this light casts at `point`, following `falloff_gradient`
+    /// from the light's position out to the edge of its reach.
+    pub fn color_at(&self, point: &Point) -> Color {
+        match self.falloff_gradient {
+            Some((inner, outer)) => {
+                let t = (self.position.distance(point) / self.intensity).clamp(0.0, 1.0);
+                inner.interpolate(outer, t)
+            }
+            None => self.color,
+        }
+    }
+}
+
+/// How a light's color is combined with the floor it falls on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LightMixing {
+    /// Each light blends toward its own color by its falloff.
+    #[default]
+    Additive,
+    /// Each channel keeps the larger of the floor and the incoming light, so a
+    /// strong light never pushes a channel past what either source holds.
+    MaxChannel,
+}
+
+/// File formats the pixel buffer can be saved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    /// Binary (P6) PPM. It has no alpha channel, so RGBA output is not
+    /// available.
+    Ppm,
+}
+
+/// Output settings for [`Map::save_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveOptions {
+    pub format: ImageFormat,
+    /// Integer nearest-neighbour scale applied to the image.
+    pub upscale: u64,
+    /// Whether to add an opaque alpha channel. Ignored for PPM.
+    pub rgba: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions {
+            format: ImageFormat::Png,
+            upscale: 1,
+            rgba: false,
+        }
+    }
+}
+
+/// Counters collected while rendering a frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    /// Number of output pixels processed.
+    pub pixels: u64,
+    /// Number of lights considered for open pixels after spatial culling.
+    pub light_evaluations: u64,
+    /// Number of line-of-sight tests performed against lights.
+    pub los_checks: u64,
+    /// Number of pixels that received light from at least one light.
+    pub lit_pixels: u64,
+    /// Whether the walls were re-baked rather than reused from the cache.
+    pub walls_baked: bool,
+    /// Wall-clock time spent rendering.
+    pub duration: Duration,
+}
+
+/// The first solid square struck by a ray cast with [`Map::cast_ray`].
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// Where the ray entered the square.
+    pub point: Point,
+    /// Grid coordinates `(x, y)` of the square that was hit.
+    pub square: (u64, u64),
+    /// Distance travelled from the ray origin.
+    pub distance: f64,
 }
 
 #[derive(Debug)]
@@ -21,10 +242,67 @@ pub struct Map {
     pub sim_scale: u64,
     pub lights: Vec<Light>,
     pub squares: Vec<Vec<bool>>,
+    /// Geometry for every floor of the map. `squares` is the working copy of
+    /// `layers[active_layer]` and is written back when the active layer changes.
+    pub layers: Vec<Vec<Vec<bool>>>,
+    pub active_layer: usize,
     pub pixel_buffer: Vec<u8>,
     pub texture: Vec<u8>,
+    pub texture_path: String,
+    pub texture_width: u32,
+    pub texture_height: u32,
+    /// Size in texels of one autotile in the atlas.
+    pub tile_width: u32,
+    pub tile_height: u32,
     pub cast_step_size: f64,
     pub rays_per_degree: f64,
+    /// Distance (in squares) the line-of-sight ray is shortened by at the lit
+    /// end, to avoid wall edges shadowing their own neighbouring pixels.
+    pub shadow_bias: f64,
+    /// Whether line of sight is also blocked where a ray squeezes diagonally
+    /// between two walls that only touch at a corner.
+    pub corner_leak_fix: bool,
+    /// Optional per-texel wall normals laid out like `texture`. The red and
+    /// green channels encode the x and y components mapped from -1..1 to 0..255.
+    pub normal_map: Option<Vec<u8>>,
+    /// Whether lights also tint the wall pixels they can reach.
+    pub light_walls: bool,
+    /// Strength of the light scattered along rays through open space. Zero
+    /// disables volumetric light shafts.
+    pub volumetric_density: f64,
+    /// Frequency of the [`Map::color_floor`] noise in radians per square, so
+    /// the pattern stays fixed to the world at any `sim_scale`.
+    pub floor_noise_scale: f64,
+    /// Camera-style exposure applied as the last step of rendering. `None`
+    /// leaves the rendered colors untouched.
+    pub exposure: Option<f64>,
+    /// Whether the grid wraps around at its edges, so squares on one edge
+    /// neighbour those on the opposite edge.
+    pub wrap_edges: bool,
+    /// How lights combine with the floor they illuminate.
+    pub light_mixing: LightMixing,
+    /// Exposure in stops for the summed light on open floor. When set, lights
+    /// are accumulated and rolled off with `1 - exp(-coverage * 2^stops)`
+    /// before being blended over the floor, in place of `light_mixing`, so
+    /// crowded lights keep their color instead of clipping.
+    pub light_exposure: Option<f64>,
+    /// Set when geometry or the floor changes so the next render rebuilds the
+    /// floor and wall base. The setters set it for you; set it yourself after
+    /// editing `squares`, `layers`, `texture`, `tile_width`, `tile_height`,
+    /// `wrap_edges`, `void_color`, `floor_noise_scale`, `sim_scale`, `width` or
+    /// `height` directly, or the next render reuses the stale base. A base that
+    /// no longer matches the size of `pixel_buffer` is always rebuilt.
+    pub dirty: bool,
+    /// Color of open floor when neither [`Map::color_floor`] nor
+    /// [`Map::set_floor_texture`] has painted it.
+    pub void_color: Color,
+    /// The floor with walls merged on top, reused by renders while not dirty.
+    base_buffer: Option<Vec<u8>>,
+    /// RGB floor image with its width and height, tiled under the walls.
+    floor_texture: Option<(Vec<u8>, u32, u32)>,
+    /// Seed of the last [`Map::color_floor`] call, so the noise floor can be
+    /// painted again whenever the base is rebuilt.
+    floor_seed: Option<f64>,
 }
 
 impl Map {
@@ -36,28 +314,245 @@ impl Map {
         cast_step_size: f64,
         rays_per_degree: f64,
     ) -> Map {
-        let reader = png::Decoder::new(File::open(texure_path).unwrap());
-        let mut reader = reader.read_info().unwrap();
-        let mut texture = vec![0; reader.output_buffer_size()];
-        reader.next_frame(&mut texture).unwrap();
-        Map {
+        Self::try_new(
+            height,
+            width,
+            sim_scale,
+            texure_path,
+            cast_step_size,
+            rays_per_degree,
+        )
+        .unwrap()
+    }
+
+    /// Creates a map like [`Map::new`], loading the texture atlas at
+    /// `texture_path`.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::Io` or `MapError::Decode` if the atlas cannot be read.
+    pub fn try_new(
+        height: u64,
+        width: u64,
+        sim_scale: u64,
+        texture_path: String,
+        cast_step_size: f64,
+        rays_per_degree: f64,
+    ) -> Result<Map, MapError> {
+        let (texture, texture_width, texture_height) = decode_png_rgba(File::open(&texture_path)?)?;
+        Ok(Map {
             height,
             width,
             sim_scale,
             lights: Vec::new(),
             squares: vec![vec![false; width as usize]; height as usize],
+            layers: vec![vec![vec![false; width as usize]; height as usize]],
+            active_layer: 0,
             pixel_buffer: vec![
                 0;
                 ((height * 8 * sim_scale) * (width * 8 * sim_scale) * 3) as usize
             ],
             texture,
+            texture_path,
+            texture_width,
+            texture_height,
+            tile_width: 8,
+            tile_height: 8,
             cast_step_size,
             rays_per_degree,
+            shadow_bias: 0.05,
+            corner_leak_fix: false,
+            normal_map: None,
+            light_walls: false,
+            volumetric_density: 0.0,
+            floor_noise_scale: 0.8,
+            exposure: None,
+            wrap_edges: false,
+            light_mixing: LightMixing::Additive,
+            light_exposure: None,
+            dirty: true,
+            void_color: Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0xff,
+            },
+            base_buffer: None,
+            floor_texture: None,
+            floor_seed: None,
+        })
+    }
+
+    /// Replaces the wall texture atlas with the PNG at `path`, keeping the
+    /// grid and lights. The atlas may differ in size from the previous one.
+    pub fn set_texture(&mut self, path: &str) -> Result<(), MapError> {
+        self.set_texture_bytes(&std::fs::read(path)?)?;
+        self.texture_path = path.to_string();
+        Ok(())
+    }
+
+    /// Replaces the wall texture atlas with PNG data held in memory. Since the
+    /// atlas has no file, `texture_path` is cleared and must be set before
+    /// the map is saved as a scene. A normal map is dropped if the new atlas
+    /// has a different size, as it no longer lines up with the tiles.
+    pub fn set_texture_bytes(&mut self, bytes: &[u8]) -> Result<(), MapError> {
+        let (texture, width, height) = decode_png_rgba(bytes)?;
+        if (width, height) != (self.texture_width, self.texture_height) {
+            self.normal_map = None;
+        }
+        self.texture = texture;
+        self.texture_width = width;
+        self.texture_height = height;
+        self.texture_path = String::new();
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Loads a normal map with the same layout as the texture atlas. While set,
+    /// wall pixels are lit by how directly their surface faces each light.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::DimensionMismatch` if the image is not the size of the atlas,
+    /// or `MapError::Io` or `MapError::Decode` if it cannot be read.
+    pub fn set_normal_map(&mut self, path: &str) -> Result<(), MapError> {
+        let (normals, width, height) = decode_png_rgba(File::open(path)?)?;
+        if width != self.texture_width {
+            return Err(MapError::DimensionMismatch {
+                what: "normal map width",
+                expected: self.texture_width as usize,
+                actual: width as usize,
+            });
+        }
+        if height != self.texture_height {
+            return Err(MapError::DimensionMismatch {
+                what: "normal map height",
+                expected: self.texture_height as usize,
+                actual: height as usize,
+            });
+        }
+        self.normal_map = Some(normals);
+        Ok(())
+    }
+
+    /// Sets the density of volumetric light shafts. Each light adds a glow to
+    /// pixels in its reach proportional to how much of the ray toward them
+    /// crosses open space, so shafts appear through gaps in walls.
+    pub fn set_volumetric(&mut self, density: f64) {
+        self.volumetric_density = density;
+    }
+
+    /// Sets the exposure used to tone map the final image. Higher values
+    /// brighten mid-tones while highlights roll off smoothly below clipping.
+    pub fn set_exposure(&mut self, exposure: f64) {
+        self.exposure = Some(exposure);
+    }
+
+    /// Sets the color of open floor that no floor generator has painted.
+    pub fn set_void_color(&mut self, color: Color) {
+        self.void_color = color;
+        self.dirty = true;
+    }
+
+    /// Sets how lights combine with the floor they illuminate.
+    pub fn set_light_mixing(&mut self, mode: LightMixing) {
+        self.light_mixing = mode;
+    }
+
+    /// Sets the exposure, in stops, applied to the summed light on open floor
+    /// before it is blended over the floor.
+    pub fn set_light_exposure(&mut self, stops: f64) {
+        self.light_exposure = Some(stops);
+    }
+
+    /// Sets whether the grid wraps around at its edges.
+    pub fn set_wrap_edges(&mut self, wrap: bool) {
+        self.wrap_edges = wrap;
+        self.dirty = true;
+    }
+
+    /// Sets the size in texels of one autotile in the atlas.
+    pub fn set_tile_size(&mut self, width: u32, height: u32) {
+        self.tile_width = width;
+        self.tile_height = height;
+        self.dirty = true;
+    }
+
+    /// Enables or disables tinting of wall pixels by nearby lights.
+    pub fn set_light_walls(&mut self, enabled: bool) {
+        self.light_walls = enabled;
+    }
+
+    /// Adds a light to the scene. A non-positive or NaN intensity is clamped to
+    /// zero, which leaves the light with no reach, and a light whose position
+    /// is not finite is dropped, so it takes no index in `lights`. Use
+    /// [`Map::try_add_light`] to reject such lights with an error instead.
+    pub fn add_light(&mut self, mut light: Light) {
+        if !light.position.is_finite() {
+            return;
+        }
+        light.intensity = light.intensity.max(0.0);
+        self.lights.push(light);
+    }
+
+    /// Turns the light at `index` on or off without removing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn set_light_enabled(&mut self, index: usize, on: bool) {
+        self.lights[index].enabled = on;
+    }
+
+    /// Adds a batch of lights to the scene, applying the same intensity clamping
+    /// and position check as [`Map::add_light`].
+    pub fn add_lights(&mut self, lights: impl IntoIterator<Item = Light>) {
+        let lights = lights.into_iter();
+        self.lights.reserve(lights.size_hint().0);
+        for light in lights {
+            self.add_light(light);
         }
     }
 
-    pub fn add_light(&mut self, light: Light) {
+    /// Returns the number of lights in the scene.
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Adds a light to the scene after checking that it can be rendered.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::InvalidLight` if the intensity is not positive or the position
+    /// is not finite.
+    pub fn try_add_light(&mut self, light: Light) -> Result<(), MapError> {
+        if light.intensity.is_nan() || light.intensity <= 0.0 {
+            return Err(MapError::InvalidLight {
+                reason: "intensity must be positive",
+            });
+        }
+        if !light.position.is_finite() {
+            return Err(MapError::InvalidLight {
+                reason: "position must be finite",
+            });
+        }
         self.lights.push(light);
+        Ok(())
+    }
+
+    /// Returns the lights in the scene as a slice.
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Returns the world position of every light, in light order.
+    pub fn light_positions(&self) -> Vec<Point> {
+        self.lights.iter().map(|light| light.position).collect()
+    }
+
+    /// Returns a mutable iterator over the lights in the scene.
+    pub fn lights_mut(&mut self) -> std::slice::IterMut<'_, Light> {
+        self.lights.iter_mut()
     }
 
     pub fn squares_from_file(&mut self, path: String) {
@@ -70,6 +565,209 @@ impl Map {
                 }
             });
         });
+        self.dirty = true;
+    }
+
+    /// Makes every square along the edge of the grid solid.
+    pub fn fill_border_walls(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        for (y, row) in self.squares.iter_mut().enumerate() {
+            for (x, square) in row.iter_mut().enumerate() {
+                if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                    *square = true;
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Clears a `w` by `h` rectangle of squares with its top-left corner at
+    /// `(x, y)`. Parts of the rectangle outside the grid are ignored.
+    pub fn carve_room(&mut self, x: u64, y: u64, w: u64, h: u64) {
+        for row in self.squares.iter_mut().skip(y as usize).take(h as usize) {
+            for square in row.iter_mut().skip(x as usize).take(w as usize) {
+                *square = false;
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Iterates over the grid in row-major order.
+    ///
+    /// # Returns
+    ///
+    /// An iterator of `(x, y, solid)` for every square.
+    pub fn iter_squares(&self) -> impl Iterator<Item = (u64, u64, bool)> + '_ {
+        self.squares.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, &solid)| (x as u64, y as u64, solid))
+        })
+    }
+
+    /// Iterates over the wall squares in row-major order.
+    ///
+    /// # Returns
+    ///
+    /// An iterator of `(x, y)` for every solid square.
+    pub fn solid_squares(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.iter_squares()
+            .filter(|&(_, _, solid)| solid)
+            .map(|(x, y, _)| (x, y))
+    }
+
+    /// Counts the wall squares in the grid.
+    pub fn solid_count(&self) -> u64 {
+        self.solid_squares().count() as u64
+    }
+
+    /// Counts the open squares in the grid.
+    pub fn open_count(&self) -> u64 {
+        self.iter_squares().filter(|&(_, _, solid)| !solid).count() as u64
+    }
+
+    /// Checks whether the open area containing `start` is sealed off from the
+    /// edge of the grid, moving between edge-adjacent open squares. With
+    /// `wrap_edges` set the grid has no edge, so the area is instead open when
+    /// it wraps around the map and joins up with itself.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the area escapes as above or `start` is outside the grid,
+    /// otherwise `true`. A solid `start` is trivially enclosed.
+    pub fn is_region_enclosed(&self, start: (u64, u64)) -> bool {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let (start_x, start_y) = (start.0 as i64, start.1 as i64);
+        if start_x >= width || start_y >= height {
+            return false;
+        }
+        if self.squares[start_y as usize][start_x as usize] {
+            return true;
+        }
+
+        // The copy of the map, in the wrapped plane, each square was reached in.
+        let mut seen = vec![vec![None; width as usize]; height as usize];
+        seen[start_y as usize][start_x as usize] = Some((0, 0));
+        let mut stack = vec![(start_x, start_y)];
+        while let Some((x, y)) = stack.pop() {
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                let copy = (nx.div_euclid(width), ny.div_euclid(height));
+                if copy != (0, 0) && !self.wrap_edges {
+                    return false;
+                }
+                let (gx, gy) = (
+                    nx.rem_euclid(width) as usize,
+                    ny.rem_euclid(height) as usize,
+                );
+                if self.squares[gy][gx] {
+                    continue;
+                }
+                match seen[gy][gx] {
+                    None => {
+                        seen[gy][gx] = Some(copy);
+                        stack.push((nx, ny));
+                    }
+                    Some(earlier) if earlier != copy => return false,
+                    Some(_) => {}
+                }
+            }
+        }
+        true
+    }
+
+    /// Checks that the public fields of the map are consistent with each other.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the map is consistent, otherwise the first violation found.
+    pub fn validate(&self) -> Result<(), MapError> {
+        self.check_dimensions()?;
+
+        for (index, light) in self.lights.iter().enumerate() {
+            let position = light.position;
+            if !(0.0..=self.width as f64).contains(&position.x)
+                || !(0.0..=self.height as f64).contains(&position.y)
+            {
+                return Err(MapError::LightOutOfBounds { index, position });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the grid and pixel buffer against `width`, `height` and
+    /// `sim_scale`.
+    fn check_dimensions(&self) -> Result<(), MapError> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        for grid in std::iter::once(&self.squares).chain(&self.layers) {
+            if grid.len() != height {
+                return Err(MapError::DimensionMismatch {
+                    what: "square rows",
+                    expected: height,
+                    actual: grid.len(),
+                });
+            }
+            if let Some(row) = grid.iter().find(|row| row.len() != width) {
+                return Err(MapError::DimensionMismatch {
+                    what: "square columns",
+                    expected: width,
+                    actual: row.len(),
+                });
+            }
+        }
+
+        let expected =
+            ((self.height * 8 * self.sim_scale) * (self.width * 8 * self.sim_scale) * 3) as usize;
+        if self.pixel_buffer.len() != expected {
+            return Err(MapError::DimensionMismatch {
+                what: "pixel buffer",
+                expected,
+                actual: self.pixel_buffer.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds an empty layer above the existing ones.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new layer.
+    pub fn add_layer(&mut self) -> usize {
+        self.layers
+            .push(vec![vec![false; self.width as usize]; self.height as usize]);
+        self.layers.len() - 1
+    }
+
+    /// Makes `index` the layer that `squares` (and therefore loading and
+    /// rendering) operates on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not a valid layer.
+    pub fn set_active_layer(&mut self, index: usize) {
+        self.layers[self.active_layer] = self.squares.clone();
+        self.squares = self.layers[index].clone();
+        self.active_layer = index;
+        self.dirty = true;
+    }
+
+    /// Renders the bottom layer with lighting and stacks the walls of every
+    /// layer above it on top, blending by texture alpha.
+    ///
+    /// Each call starts again from the unlit layer-0 base, so calling it twice
+    /// gives the same result and later renders never see the upper layers.
+    pub fn composite_layers(&mut self) {
+        let active = self.active_layer;
+        self.set_active_layer(0);
+        self.render();
+        for index in 1..self.layers.len() {
+            self.set_active_layer(index);
+            let layer = self.color_walls();
+            merge_layer(&mut self.pixel_buffer, &layer);
+        }
+        self.set_active_layer(active);
     }
 
     pub fn color_walls(&self) -> Vec<u8> {
@@ -77,21 +775,13 @@ impl Map {
         let mut i = 0;
         for y in 0..self.height * 8 * self.sim_scale {
             for x in 0..self.width * 8 * self.sim_scale {
-                let scaled_point = Point {
-                    x: x as f64 / 8. / self.sim_scale as f64,
-                    y: y as f64 / 8. / self.sim_scale as f64,
-                };
+                let scaled_point = self.screen_to_world(x, y);
                 if self.is_within_square(&scaled_point) {
                     let bitmask = self.get_surrounding_square_bitmap(&scaled_point);
 
                     let (tex_x, tex_y) = self.get_tex_cord(&scaled_point, bitmask);
 
-                    let color = Color {
-                        r: self.texture[(tex_y * 64 + tex_x) as usize * 4],
-                        g: self.texture[(tex_y * 64 + tex_x) as usize * 4 + 1],
-                        b: self.texture[(tex_y * 64 + tex_x) as usize * 4 + 2],
-                        a: self.texture[(tex_y * 64 + tex_x) as usize * 4 + 3],
-                    };
+                    let color = self.texel(&self.texture, tex_x, tex_y);
                     layer[i] = color.r;
                     layer[i + 1] = color.g;
                     layer[i + 2] = color.b;
@@ -103,6 +793,71 @@ impl Map {
         layer
     }
 
+    /// Extracts the atlas tile that autotiling selects for a neighbour bitmask.
+    ///
+    /// # Returns
+    ///
+    /// The tile as an RGBA buffer of `tile_width` by `tile_height` pixels.
+    pub fn tile_for_bitmask(&self, bitmask: u8) -> Vec<u8> {
+        let (tile_x, tile_y) = self.get_tex_cord(&Point::new(0.0, 0.0), bitmask);
+        let mut tile = Vec::with_capacity((self.tile_width * self.tile_height * 4) as usize);
+        for y in 0..self.tile_height {
+            for x in 0..self.tile_width {
+                let color = self.texel(&self.texture, tile_x + x, tile_y + y);
+                tile.extend_from_slice(&[color.r, color.g, color.b, color.a]);
+            }
+        }
+        tile
+    }
+
+    /// Saves every autotile case side by side as a PNG, for checking the
+    /// bitmask table at a glance.
+    ///
+    /// Bitmask `n` is drawn at column `n % 16`, row `n / 16` of a 16 x 16 grid,
+    /// so 8px tiles produce a 128 x 128 image.
+    pub fn export_tileset_atlas(&self, path: &str) -> Result<(), MapError> {
+        let atlas_width = self.tile_width * 16;
+        let atlas_height = self.tile_height * 16;
+        let mut atlas = vec![0; (atlas_width * atlas_height * 4) as usize];
+        let row_len = (self.tile_width * 4) as usize;
+        for bitmask in 0..=255u8 {
+            let tile = self.tile_for_bitmask(bitmask);
+            let origin_x = (bitmask as u32 % 16) * self.tile_width;
+            let origin_y = (bitmask as u32 / 16) * self.tile_height;
+            for (y, row) in tile.chunks(row_len).enumerate() {
+                let start = (((origin_y + y as u32) * atlas_width + origin_x) * 4) as usize;
+                atlas[start..start + row_len].copy_from_slice(row);
+            }
+        }
+        try_write_png(
+            path,
+            atlas_width,
+            atlas_height,
+            png::ColorType::Rgba,
+            &atlas,
+        )
+    }
+
+    /// Reads a texel from the texture atlas.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(x, y)` lies outside the atlas.
+    pub fn texture_texel(&self, x: u32, y: u32) -> Color {
+        assert!(x < self.texture_width && y < self.texture_height);
+        self.texel(&self.texture, x, y)
+    }
+
+    fn texel(&self, texture: &[u8], tex_x: u32, tex_y: u32) -> Color {
+        let i = (tex_y * self.texture_width + tex_x) as usize * 4;
+        Color {
+            r: texture[i],
+            g: texture[i + 1],
+            b: texture[i + 2],
+            a: texture[i + 3],
+        }
+    }
+
     fn create_pixel_layer(&self) -> Vec<u8> {
         vec![
             0;
@@ -110,103 +865,201 @@ impl Map {
         ]
     }
 
-    fn merge_pixel_layer(&mut self, other: Vec<u8>) {
-        let mut selfi = 0;
-        let mut otheri = 0;
-        while selfi < self.pixel_buffer.len() {
-            let self_color = Color {
-                r: self.pixel_buffer[selfi],
-                g: self.pixel_buffer[selfi + 1],
-                b: self.pixel_buffer[selfi + 2],
-                a: 0xff,
-            };
-            let other_color = Color {
-                r: other[otheri],
-                g: other[otheri + 1],
-                b: other[otheri + 2],
-                a: 0xff,
-            };
-            let factor = other[otheri + 3] as f64 / 255.0;
-
-            let new_color = other_color.blend(self_color, factor);
-            // println!("{:?}", otherColor);
-            self.pixel_buffer[selfi] = new_color.r;
-            self.pixel_buffer[selfi + 1] = new_color.g;
-            self.pixel_buffer[selfi + 2] = new_color.b;
+    /// Loads a PNG to tile across the floor in place of the noise from
+    /// [`Map::color_floor`]. One texel covers one eighth of a square, like the
+    /// wall atlas, and the image repeats by world position.
+    pub fn set_floor_texture(&mut self, path: &str) -> Result<(), MapError> {
+        let (rgba, width, height) = decode_png_rgba(File::open(path)?)?;
+        let rgb = rgba
+            .chunks(4)
+            .flat_map(|texel| [texel[0], texel[1], texel[2]])
+            .collect();
+        self.floor_texture = Some((rgb, width, height));
+        self.dirty = true;
+        Ok(())
+    }
 
-            selfi += 3;
-            otheri += 4;
+    fn tile_floor_texture(&self, buffer: &mut [u8]) {
+        let Some((texture, tex_width, tex_height)) = &self.floor_texture else {
+            return;
+        };
+        let mut i = 0;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let tex_x = (x / self.sim_scale) % *tex_width as u64;
+                let tex_y = (y / self.sim_scale) % *tex_height as u64;
+                let t = ((tex_y * *tex_width as u64 + tex_x) * 3) as usize;
+                buffer[i..i + 3].copy_from_slice(&texture[t..t + 3]);
+                i += 3;
+            }
         }
     }
 
     pub fn color_floor(&mut self, seed: f64) {
+        self.floor_texture = None;
+        self.floor_seed = Some(seed);
+        let mut buffer = std::mem::take(&mut self.pixel_buffer);
+        self.paint_floor_noise(seed, &mut buffer);
+        self.pixel_buffer = buffer;
+        self.dirty = true;
+    }
+
+    fn paint_floor_noise(&self, seed: f64, buffer: &mut [u8]) {
         let mut i = 0;
         for y in 0..self.height * 8 * self.sim_scale {
             for x in 0..self.width * 8 * self.sim_scale {
-                let point = Point {
-                    x: x as f64 / self.sim_scale as f64,
-                    y: y as f64 / self.sim_scale as f64,
-                };
+                let point = self.screen_to_world(x, y);
 
                 // Use a combination of sin and cos with the seed for texture variation
-                let noise_value =
-                    ((point.x * 0.1 + seed).sin() + (point.y * 0.1 + seed).cos()) * 0.5;
+                let noise_value = ((point.x * self.floor_noise_scale + seed).sin()
+                    + (point.y * self.floor_noise_scale + seed).cos())
+                    * 0.5;
                 let noise_intensity = (noise_value * 20.0) as i32; // Adjust noise intensity
 
                 // Base color values
                 let base_color = 0x83;
-                let r = (base_color as i32 + noise_intensity).clamp(0, 0xff) as u8;
-                let g = (base_color as i32 + noise_intensity).clamp(0, 0xff) as u8;
-                let b = (base_color as i32 + noise_intensity).clamp(0, 0xff) as u8;
+                let r = (base_color + noise_intensity).clamp(0, 0xff) as u8;
+                let g = (base_color + noise_intensity).clamp(0, 0xff) as u8;
+                let b = (base_color + noise_intensity).clamp(0, 0xff) as u8;
 
                 let color = Color { r, g, b, a: 0xff };
 
-                self.pixel_buffer[i] = color.r;
-                self.pixel_buffer[i + 1] = color.g;
-                self.pixel_buffer[i + 2] = color.b;
+                buffer[i] = color.r;
+                buffer[i + 1] = color.g;
+                buffer[i + 2] = color.b;
                 i += 3;
             }
         }
     }
 
     pub fn render(&mut self) {
-        // let seed = rand::thread_rng().gen::<f64>();
-        // self.color_floor(seed);
-        let layer = self.color_walls();
-        self.merge_pixel_layer(layer);
+        self.render_stats();
+    }
 
-        if self.lights.len() == 0 {
-            return;
+    /// Renders like [`Map::render`], but first checks that the grid and pixel
+    /// buffer still match the map dimensions, e.g. after `width`, `height` or
+    /// `sim_scale` were changed directly.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::DimensionMismatch` without rendering if they do not match.
+    pub fn try_render(&mut self) -> Result<(), MapError> {
+        self.check_dimensions()?;
+        self.render();
+        Ok(())
+    }
+
+    /// Renders the scene and returns it with an alpha channel.
+    ///
+    /// The RGB channels match [`Map::render`], where wall texels are already
+    /// blended over the floor by their alpha. Wall pixels keep their texel alpha
+    /// so transparent cutouts can be masked downstream; open pixels are opaque.
+    pub fn render_rgba(&mut self) -> Vec<u8> {
+        let walls = self.color_walls();
+        self.render();
+        let mut rgba = self.create_pixel_layer();
+        let mut i = 0;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let scaled_point = self.screen_to_world(x, y);
+                let rgb = i / 4 * 3;
+                rgba[i..i + 3].copy_from_slice(&self.pixel_buffer[rgb..rgb + 3]);
+                rgba[i + 3] = if self.is_within_square(&scaled_point) {
+                    walls[i + 3]
+                } else {
+                    0xff
+                };
+                i += 4;
+            }
         }
+        rgba
+    }
 
+    /// Renders the scene with a wall mask in the alpha channel.
+    ///
+    /// # Returns
+    ///
+    /// An RGBA buffer whose RGB channels match [`Map::render`] and whose alpha
+    /// is 255 on wall pixels and 0 on open floor, for masking layers composited
+    /// over the map.
+    pub fn render_with_mask(&mut self) -> Vec<u8> {
+        self.render();
+        let mut rgba = self.create_pixel_layer();
         let mut i = 0;
         for y in 0..self.height * 8 * self.sim_scale {
             for x in 0..self.width * 8 * self.sim_scale {
-                let scaled_point = Point {
-                    x: x as f64 / 8. / self.sim_scale as f64,
-                    y: y as f64 / 8. / self.sim_scale as f64,
+                let scaled_point = self.screen_to_world(x, y);
+                let rgb = i / 4 * 3;
+                rgba[i..i + 3].copy_from_slice(&self.pixel_buffer[rgb..rgb + 3]);
+                rgba[i + 3] = if self.is_within_square(&scaled_point) {
+                    0xff
+                } else {
+                    0
                 };
+                i += 4;
+            }
+        }
+        rgba
+    }
+
+    /// Renders a quick preview at a `sim_scale` of 1, regardless of the
+    /// configured scale. The map's own pixel buffer is left untouched.
+    ///
+    /// # Returns
+    ///
+    /// An RGB buffer of `width * 8` by `height * 8` pixels.
+    pub fn render_preview(&mut self) -> Vec<u8> {
+        let sim_scale = std::mem::replace(&mut self.sim_scale, 1);
+        let preview = vec![0; (self.height * 8 * self.width * 8 * 3) as usize];
+        let pixel_buffer = std::mem::replace(&mut self.pixel_buffer, preview);
+        let base_buffer = self.base_buffer.take();
+        let dirty = std::mem::replace(&mut self.dirty, true);
+        self.render();
+        self.sim_scale = sim_scale;
+        self.base_buffer = base_buffer;
+        self.dirty = dirty;
+        std::mem::replace(&mut self.pixel_buffer, pixel_buffer)
+    }
+
+    /// Renders the scene like [`Map::render`] while counting the work done.
+    ///
+    /// # Returns
+    ///
+    /// A `RenderStats` describing the render that just completed.
+    pub fn render_stats(&mut self) -> RenderStats {
+        let start = Instant::now();
+        let mut stats = RenderStats {
+            pixels: self.height * 8 * self.sim_scale * self.width * 8 * self.sim_scale,
+            ..Default::default()
+        };
+
+        // let seed = rand::thread_rng().gen::<f64>();
+        // self.color_floor(seed);
+        stats.walls_baked = self.bake_base();
+        if let Some(base) = &self.base_buffer {
+            self.pixel_buffer.clone_from(base);
+        }
 
-                let mut pixel_color = Color {
+        if self.lights.is_empty() {
+            self.apply_exposure();
+            stats.duration = start.elapsed();
+            return stats;
+        }
+
+        let buckets = self.light_buckets();
+
+        let mut i = 0;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let base = Color {
                     r: self.pixel_buffer[i],
                     g: self.pixel_buffer[i + 1],
                     b: self.pixel_buffer[i + 2],
                     a: 0xff,
                 };
-
-                if !self.is_within_square(&scaled_point) {
-                    for light in &self.lights {
-                        let distance = ((light.position.x - scaled_point.x).powi(2)
-                            + (light.position.y - scaled_point.y).powi(2))
-                        .sqrt();
-
-                        if distance < light.intensity
-                            && self.point_has_los(&light.position, &scaled_point)
-                        {
-                            let factor = 1.0 - distance / light.intensity;
-                            pixel_color = light.color.blend(pixel_color, factor);
-                        }
-                    }
+                let (pixel_color, lit) = self.shade_pixel(x, y, base, &buckets, &mut stats);
+                if lit {
+                    stats.lit_pixels += 1;
                 }
                 self.pixel_buffer[i] = pixel_color.r;
                 self.pixel_buffer[i + 1] = pixel_color.g;
@@ -214,385 +1067,1546 @@ impl Map {
                 i += 3;
             }
         }
-    }
 
-    pub fn save(&self, path: &str) {
-        let mut encoder = png::Encoder::new(
-            File::create(path).unwrap(),
-            (self.width * 8 * self.sim_scale) as u32,
-            (self.height * 8 * self.sim_scale) as u32,
-        );
-        encoder.set_color(png::ColorType::Rgb);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().unwrap();
-        writer.write_image_data(&self.pixel_buffer).unwrap();
-        writer.finish().unwrap();
+        self.apply_exposure();
+        stats.duration = start.elapsed();
+        stats
     }
 
-    pub fn save_upscaled(&self, path: &str, scale: u64) {
-        let start_height = self.height * 8 * self.sim_scale;
-        let start_width = self.width * 8 * self.sim_scale;
-        let end_height = start_height * scale;
-        let end_width = start_width * scale;
-        let mut pixel_buffer: Vec<u8> = vec![0; (end_height * end_width * 3) as usize];
-        for y in 0..end_height {
-            for x in 0..end_width {
-                let source_x = x / scale;
-                let source_y = y / scale;
-                let source_index = (source_y * start_width + source_x) * 3;
-                let dest_index = (y * end_width + x) * 3;
-                pixel_buffer[dest_index as usize] = self.pixel_buffer[source_index as usize];
-                pixel_buffer[dest_index as usize + 1] =
-                    self.pixel_buffer[source_index as usize + 1];
-                pixel_buffer[dest_index as usize + 2] =
-                    self.pixel_buffer[source_index as usize + 2];
-            }
-        }
-        let mut encoder = png::Encoder::new(
-            File::create(path).unwrap(),
-            (end_width) as u32,
-            (end_height) as u32,
-        );
-        encoder.set_color(png::ColorType::Rgb);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().unwrap();
-        writer.write_image_data(&pixel_buffer).unwrap();
-        writer.finish().unwrap();
-    }
-    #[inline]
-    fn is_within_square(&self, point: &Point) -> bool {
-        let grid_x = (point.x) as usize;
-        let grid_y = (point.y) as usize;
-        if grid_x < self.width as usize && grid_y < self.height as usize {
-            self.squares[grid_y][grid_x]
-        } else {
-            false
-        }
-    }
+    /// Renders only a rectangle of the output, in output pixel coordinates.
+    /// Lighting and walls match the same pixels of a full [`Map::render`],
+    /// and the map's pixel buffer, holding the last full render, is left
+    /// untouched.
+    ///
+    /// # Returns
+    ///
+    /// An RGB buffer of `w` by `h` pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle extends past the output image.
+    pub fn render_region(&mut self, x: u64, y: u64, w: u64, h: u64) -> Vec<u8> {
+        let width = self.width * 8 * self.sim_scale;
+        assert!(x + w <= width && y + h <= self.height * 8 * self.sim_scale);
+        self.bake_base();
+        let buckets = self.light_buckets();
+        let base = self.base_buffer.as_deref().unwrap();
+        let mut stats = RenderStats::default();
 
-    fn get_root_square(&self, point: &Point) -> Point {
-        Point {
-            x: ((point.x * 8.) % 8.).floor(),
-            y: ((point.y * 8.) % 8.).floor(),
+        let mut region = Vec::with_capacity((w * h * 3) as usize);
+        for py in y..y + h {
+            for px in x..x + w {
+                let i = ((py * width + px) * 3) as usize;
+                let color = Color {
+                    r: base[i],
+                    g: base[i + 1],
+                    b: base[i + 2],
+                    a: 0xff,
+                };
+                let (color, _) = self.shade_pixel(px, py, color, &buckets, &mut stats);
+                region.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+        }
+        if let Some(exposure) = self.exposure {
+            expose(&mut region, exposure);
         }
+        region
     }
 
-    fn point_has_los(&self, a: &Point, b: &Point) -> bool {
-        let dx = b.x - a.x;
-        let dy = b.y - a.y;
-        let distance = (dx.powi(2) + dy.powi(2)).sqrt();
-
-        let steps = distance.ceil() as usize * 20;
-        let dx = dx / steps as f64;
-        let dy = dy / steps as f64;
-
-        for i in 0..steps {
-            let x = a.x + dx * i as f64;
-            let y = a.y + dy * i as f64;
-
-            if self.is_within_square(&Point { x, y }) {
-                return false;
+    /// Rebuilds the cached floor and wall base if the map is dirty. The base
+    /// is built in its own buffer, with the floor painted afresh from its
+    /// source, so the pixel buffer keeps the last lit frame.
+    ///
+    /// # Returns
+    ///
+    /// Whether the base was rebuilt.
+    fn bake_base(&mut self) -> bool {
+        let fits = self
+            .base_buffer
+            .as_ref()
+            .is_some_and(|base| base.len() == self.pixel_buffer.len());
+        if fits && !self.dirty {
+            return false;
+        }
+        let mut base = vec![0; self.pixel_buffer.len()];
+        if self.floor_texture.is_some() {
+            self.tile_floor_texture(&mut base);
+        } else if let Some(seed) = self.floor_seed {
+            self.paint_floor_noise(seed, &mut base);
+        } else {
+            let void = self.void_color;
+            for pixel in base.chunks_mut(3) {
+                pixel.copy_from_slice(&[void.r, void.g, void.b]);
             }
         }
-
+        merge_layer(&mut base, &self.color_walls());
+        self.base_buffer = Some(base);
+        self.dirty = false;
         true
     }
 
-    fn get_surrounding_square_bitmap(&self, point: &Point) -> u8 {
-        let mut bitmap: u8 = 0;
+    /// Lights a single output pixel over its unlit `base` color.
+    ///
+    /// # Returns
+    ///
+    /// The lit color and whether any light reached the pixel.
+    fn shade_pixel(
+        &self,
+        x: u64,
+        y: u64,
+        base: Color,
+        buckets: &[Vec<usize>],
+        stats: &mut RenderStats,
+    ) -> (Color, bool) {
+        let scaled_point = self.screen_to_world(x, y);
 
-        let grid_x = (point.x) as usize;
-        let grid_y = (point.y) as usize;
+        let mut pixel_color = base;
+        let mut lit = false;
+        if !self.is_within_square(&scaled_point) {
+            let cell = scaled_point.y as usize * self.width as usize + scaled_point.x as usize;
+            // Weighted light color and total falloff, used by `light_exposure`.
+            let mut light_sum = [0.0; 3];
+            let mut coverage = 0.0;
+            for light in buckets[cell].iter().map(|&index| &self.lights[index]) {
+                stats.light_evaluations += 1;
+                // Split from `light_factor` so volumetric scatter can use the
+                // falloff without line of sight; keep the two in step.
+                if let Some(factor) = self.light_falloff(light, &scaled_point) {
+                    stats.los_checks += 1;
+                    let has_los = self.light_visible(light, &scaled_point);
+                    if has_los && self.light_exposure.is_some() {
+                        let color = light.color_at(&scaled_point);
+                        light_sum[0] += color.r as f64 * factor;
+                        light_sum[1] += color.g as f64 * factor;
+                        light_sum[2] += color.b as f64 * factor;
+                        coverage += factor;
+                        lit = true;
+                    } else if has_los {
+                        pixel_color = match self.light_mixing {
+                            LightMixing::Additive => {
+                                light.color_at(&scaled_point).blend(pixel_color, factor)
+                            }
+                            LightMixing::MaxChannel => {
+                                let incoming =
+                                    light.color_at(&scaled_point).scale_brightness(factor);
+                                Color {
+                                    r: pixel_color.r.max(incoming.r),
+                                    g: pixel_color.g.max(incoming.g),
+                                    b: pixel_color.b.max(incoming.b),
+                                    a: pixel_color.a,
+                                }
+                            }
+                        };
+                        lit = true;
+                    }
+                    if self.volumetric_density > 0.0 {
+                        let open = if has_los {
+                            1.0
+                        } else {
+                            self.open_fraction(&light.position, &scaled_point)
+                        };
+                        let scatter = factor * open * self.volumetric_density;
+                        pixel_color = light.color_at(&scaled_point).blend(pixel_color, scatter);
+                    }
+                }
+            }
+            if let Some(stops) = self.light_exposure.filter(|_| coverage > 0.0) {
+                let average = Color {
+                    r: (light_sum[0] / coverage).round() as u8,
+                    g: (light_sum[1] / coverage).round() as u8,
+                    b: (light_sum[2] / coverage).round() as u8,
+                    a: 0xff,
+                };
+                let strength = 1.0 - (-coverage * 2f64.powf(stops)).exp();
+                pixel_color = average.blend(pixel_color, strength);
+            }
+        } else if let Some(normal_map) = &self.normal_map {
+            let bitmask = self.get_surrounding_square_bitmap(&scaled_point);
+            let (tex_x, tex_y) = self.get_tex_cord(&scaled_point, bitmask);
+            let texel = self.texel(normal_map, tex_x, tex_y);
+            let normal = Point {
+                x: texel.r as f64 / 127.5 - 1.0,
+                y: texel.g as f64 / 127.5 - 1.0,
+            }
+            .normalize();
+            // Test visibility from one texel outside the wall face.
+            let surface = scaled_point.offset_by(&normal, 1. / 8.);
 
-        if grid_x > 0 && grid_y > 0 && self.squares[grid_y - 1][grid_x - 1] {
-            bitmap |= 0b1000_0000;
+            for light in self.lights.iter().filter(|light| light.enabled) {
+                if let Some(falloff) = self.light_falloff(light, &scaled_point) {
+                    stats.los_checks += 1;
+                    if self.light_visible(light, &surface) {
+                        let facing = normal.dot(&scaled_point.direction_to(&light.position));
+                        if facing > 0.0 {
+                            let factor = falloff * facing;
+                            pixel_color = light.color_at(&scaled_point).blend(pixel_color, factor);
+                            lit = true;
+                        }
+                    }
+                }
+            }
+        } else if self.light_walls {
+            for light in self.lights.iter().filter(|light| light.enabled) {
+                if let Some(factor) = self.light_falloff(light, &scaled_point) {
+                    stats.los_checks += 1;
+                    let face = self.nearest_wall_face(&scaled_point);
+                    if self.light_visible(light, &face) {
+                        let tinted = pixel_color
+                            .blend_with_mode(light.color_at(&scaled_point), BlendMode::Multiply);
+                        pixel_color = tinted.blend(pixel_color, factor);
+                        lit = true;
+                    }
+                }
+            }
         }
+        (pixel_color, lit)
+    }
 
-        if grid_y > 0 && self.squares[grid_y - 1][grid_x] {
-            bitmap |= 0b0100_0000;
+    /// Rolls off highlights with `1 - exp(-c * exposure)` on each normalized
+    /// channel, if an exposure has been set.
+    fn apply_exposure(&mut self) {
+        if let Some(exposure) = self.exposure {
+            expose(&mut self.pixel_buffer, exposure);
         }
+    }
 
-        if grid_x < self.width as usize - 1 && grid_y > 0 && self.squares[grid_y - 1][grid_x + 1] {
-            bitmap |= 0b0010_0000;
-        }
+    /// Renders only the light contribution, without walls or floor.
+    ///
+    /// # Returns
+    ///
+    /// An RGBA layer the size of the pixel buffer. RGB holds the accumulated
+    /// light color and alpha the total light coverage, so pixels no light
+    /// reaches (including walls) are fully transparent.
+    pub fn render_lightmap(&self) -> Vec<u8> {
+        let mut layer = self.create_pixel_layer();
+        let buckets = self.light_buckets();
 
-        if grid_x > 0 && self.squares[grid_y][grid_x - 1] {
-            bitmap |= 0b0001_0000;
+        let mut i = 0;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let scaled_point = self.screen_to_world(x, y);
+                if !self.is_within_square(&scaled_point) {
+                    let cell =
+                        scaled_point.y as usize * self.width as usize + scaled_point.x as usize;
+                    let mut color = Color {
+                        r: 0,
+                        g: 0,
+                        b: 0,
+                        a: 0,
+                    };
+                    let mut coverage = 0.0;
+                    for light in buckets[cell].iter().map(|&index| &self.lights[index]) {
+                        if let Some(factor) = self.light_factor(light, &scaled_point) {
+                            color = light.color_at(&scaled_point).blend(color, factor);
+                            coverage += factor;
+                        }
+                    }
+                    layer[i] = color.r;
+                    layer[i + 1] = color.g;
+                    layer[i + 2] = color.b;
+                    layer[i + 3] = (coverage * 255.0).clamp(0.0, 255.0) as u8;
+                }
+                i += 4;
+            }
         }
+        layer
+    }
 
-        if grid_x < self.width as usize - 1 && self.squares[grid_y][grid_x + 1] {
-            bitmap |= 0b0000_1000;
-        }
+    /// Renders the occlusion term of a single light for debugging shadows.
+    ///
+    /// # Returns
+    ///
+    /// An RGB buffer the size of the pixel buffer that is white where the
+    /// light has line of sight and black where it is occluded (including wall
+    /// pixels; a light that casts no shadows is never occluded), ignoring the
+    /// light's color and falloff. Fails with `MapError::LightIndexOutOfRange`
+    /// if there is no light at `light_index`.
+    pub fn render_shadow_map(&self, light_index: usize) -> Result<Vec<u8>, MapError> {
+        let light = self
+            .lights
+            .get(light_index)
+            .ok_or(MapError::LightIndexOutOfRange {
+                index: light_index,
+                count: self.lights.len(),
+            })?;
 
-        if grid_x > 0 && grid_y < self.height as usize - 1 && self.squares[grid_y + 1][grid_x - 1] {
-            bitmap |= 0b0000_0100;
+        let mut buffer = vec![0; self.pixel_buffer.len()];
+        let mut i = 0;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let scaled_point = self.screen_to_world(x, y);
+                if !self.is_within_square(&scaled_point) && self.light_visible(light, &scaled_point)
+                {
+                    buffer[i..i + 3].fill(0xff);
+                }
+                i += 3;
+            }
         }
+        Ok(buffer)
+    }
 
-        if grid_y < self.height as usize - 1 && self.squares[grid_y + 1][grid_x] {
-            bitmap |= 0b0000_0010;
-        }
+    /// Sums the falloff of every light that reaches `point` with line of sight.
+    ///
+    /// # Returns
+    ///
+    /// The total light level at `point`, where each light contributes between
+    /// 0.0 (at the edge of its reach) and 1.0 (at its position).
+    pub fn light_at(&self, point: &Point) -> f64 {
+        self.lights
+            .iter()
+            .filter_map(|light| self.light_factor(light, point))
+            .sum()
+    }
 
-        if grid_x < self.width as usize - 1
-            && grid_y < self.height as usize - 1
-            && self.squares[grid_y + 1][grid_x + 1]
-        {
-            bitmap |= 0b0000_0001;
+    /// Finds the lights that illuminate a point.
+    ///
+    /// # Returns
+    ///
+    /// The indices of the enabled lights that have `point` within reach and
+    /// line of sight, in light order.
+    pub fn lights_affecting(&self, point: Point) -> Vec<usize> {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| self.light_factor(light, &point).is_some())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Computes what a single light adds at a point.
+    ///
+    /// This uses the same falloff and line-of-sight test as [`Map::render`],
+    /// which blends the light's color over the floor by that falloff, so over
+    /// a black floor a single light renders as its contribution.
+    ///
+    /// # Returns
+    ///
+    /// The light's color scaled by its falloff at `point`, with alpha holding
+    /// the falloff itself. Transparent black if the light is disabled, out of
+    /// reach, or has no line of sight to `point`.
+    pub fn light_contribution(&self, light: &Light, point: &Point) -> Color {
+        match self.light_factor(light, point) {
+            Some(factor) => light
+                .color_at(point)
+                .scale_brightness(factor)
+                .with_alpha((factor * 255.0).round() as u8),
+            None => Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
         }
+    }
 
-        bitmap
+    /// The falloff of an enabled light that reaches `point` with line of sight.
+    fn light_factor(&self, light: &Light, point: &Point) -> Option<f64> {
+        if !light.enabled {
+            return None;
+        }
+        self.light_falloff(light, point)
+            .filter(|_| self.light_visible(light, point))
     }
 
-    fn get_tex_cord(&self, point: &Point, bitmap: u8) -> (u32, u32) {
-        let x: u32;
-        let y: u32;
-        let root_square = self.get_root_square(point);
+    /// Linear falloff of a light at `point`, ignoring occlusion, or `None`
+    /// beyond its reach.
+    fn light_falloff(&self, light: &Light, point: &Point) -> Option<f64> {
+        let distance = light.position.distance(point);
+        (distance < light.intensity).then(|| 1.0 - distance / light.intensity)
+    }
 
-        // println!("{:?}", root_square);
+    /// Determines which open squares are illuminated, sampling at each square's
+    /// center.
+    ///
+    /// # Returns
+    ///
+    /// A grid shaped like `squares`, `true` where the light level exceeds
+    /// `threshold`. Wall squares are never lit.
+    pub fn lit_squares(&self, threshold: f64) -> Vec<Vec<bool>> {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let center = Point {
+                            x: x as f64 + 0.5,
+                            y: y as f64 + 0.5,
+                        };
+                        !self.is_within_square(&center) && self.light_at(&center) > threshold
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 
-        match bitmap {
-            0b000_00_000 => {
-                x = 56;
-                y = 0;
-            }
-            0b100_00_000 => {
-                x = 56;
-                y = 0;
-            }
-            0b010_00_000 => {
-                x = 48;
-                y = 16;
-            }
-            0b001_00_000 => {
-                x = 56;
-                y = 0;
-            }
-            0b000_10_000 => {
-                x = 16;
-                y = 24;
-            }
-            0b000_01_000 => {
-                x = 0;
-                y = 24;
-            }
-            0b000_00_100 => {
-                x = 56;
-                y = 0;
-            }
-            0b000_00_010 => {
-                x = 48;
-                y = 0;
-            }
-            0b000_00_001 => {
-                x = 56;
-                y = 0;
-            }
-            0b000_00_011 => {
-                x = 48;
-                y = 0;
-            }
-            0b000_00_101 => {
-                x = 56;
-                y = 0;
-            }
-            0b000_00_110 => {
-                x = 48;
-                y = 0;
-            }
-            0b000_00_111 => {
-                x = 48;
-                y = 0;
-            }
-            0b000_01_001 => {
-                x = 0;
-                y = 24;
-            }
-            0b000_01_010 => {
-                x = 56;
-                y = 16;
-            }
-            0b000_01_011 => {
-                x = 0;
-                y = 0;
-            }
-            0b000_01_100 => {
-                x = 0;
-                y = 24;
-            }
-            0b000_01_101 => {
-                x = 0;
-                y = 24;
-            }
-            0b000_01_110 => {
-                x = 56;
-                y = 16;
-            }
-            0b000_01_111 => {
-                x = 0;
-                y = 0;
-            }
-            0b000_10_001 => {
-                x = 16;
-                y = 24;
-            }
-            0b000_10_010 => {
-                x = 56;
-                y = 8;
-            }
-            0b000_10_011 => {
-                x = 56;
-                y = 8;
-            }
-            0b000_10_100 => {
-                x = 16;
-                y = 24;
-            }
-            0b000_10_101 => {
-                x = 16;
-                y = 24;
-            }
-            0b000_10_110 => {
-                x = 16;
-                y = 0;
-            }
-            0b000_10_111 => {
-                x = 16;
-                y = 0;
-            }
-            0b000_11_000 => {
-                x = 8;
-                y = 24;
-            }
-            0b000_11_001 => {
-                x = 8;
-                y = 24;
+    /// Measures how much of the open floor is lit, sampling `light_at` at
+    /// every output pixel.
+    ///
+    /// # Returns
+    ///
+    /// The fraction, from 0.0 to 1.0, of non-wall pixels whose light level
+    /// exceeds `threshold`. A map with no open pixels reports 0.0.
+    pub fn illuminated_fraction(&self, threshold: f64) -> f64 {
+        let mut open = 0u64;
+        let mut lit = 0u64;
+        for y in 0..self.height * 8 * self.sim_scale {
+            for x in 0..self.width * 8 * self.sim_scale {
+                let point = self.screen_to_world(x, y);
+                if self.is_within_square(&point) {
+                    continue;
+                }
+                open += 1;
+                if self.light_at(&point) > threshold {
+                    lit += 1;
+                }
             }
+        }
+        if open == 0 {
+            return 0.0;
+        }
+        lit as f64 / open as f64
+    }
 
-            0b000_11_010 => {
-                x = 8;
-                y = 40;
-            }
+    /// Casts a ray through the grid and finds the first solid square it enters.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - Where the ray starts, in squares.
+    /// * `direction` - The ray angle in radians, measured from the positive x axis.
+    /// * `max_dist` - How far to search before giving up.
+    ///
+    /// # Returns
+    ///
+    /// The hit, or `None` if no solid square lies within `max_dist`.
+    pub fn cast_ray(&self, origin: Point, direction: f64, max_dist: f64) -> Option<RayHit> {
+        let dir = Point::new(direction.cos(), direction.sin());
+        let mut cell_x = origin.x.floor() as i64;
+        let mut cell_y = origin.y.floor() as i64;
+        let step_x = if dir.x < 0.0 { -1 } else { 1 };
+        let step_y = if dir.y < 0.0 { -1 } else { 1 };
+        let delta_x = (1.0 / dir.x).abs();
+        let delta_y = (1.0 / dir.y).abs();
+        let mut next_x = if dir.x < 0.0 {
+            (origin.x - cell_x as f64) * delta_x
+        } else {
+            (cell_x as f64 + 1.0 - origin.x) * delta_x
+        };
+        let mut next_y = if dir.y < 0.0 {
+            (origin.y - cell_y as f64) * delta_y
+        } else {
+            (cell_y as f64 + 1.0 - origin.y) * delta_y
+        };
 
-            0b000_11_011 => {
-                x = 16;
-                y = 40;
+        let mut distance = 0.0;
+        while distance <= max_dist {
+            let in_x = (0..self.width as i64).contains(&cell_x);
+            let in_y = (0..self.height as i64).contains(&cell_y);
+            if in_x && in_y && self.squares[cell_y as usize][cell_x as usize] {
+                return Some(RayHit {
+                    point: origin + dir * distance,
+                    square: (cell_x as u64, cell_y as u64),
+                    distance,
+                });
+            }
+            // Once outside the grid and heading away from it nothing can be hit.
+            if (!in_x && (cell_x < 0) == (step_x < 0)) || (!in_y && (cell_y < 0) == (step_y < 0)) {
+                return None;
             }
 
-            0b000_11_100 => {
-                x = 8;
-                y = 24;
+            if next_x < next_y {
+                distance = next_x;
+                next_x += delta_x;
+                cell_x += step_x;
+            } else {
+                distance = next_y;
+                next_y += delta_y;
+                cell_y += step_y;
             }
+        }
+        None
+    }
 
-            0b000_11_101 => {
-                x = 8;
-                y = 24;
+    /// Adds a soft glow around bright pixels of the rendered buffer.
+    ///
+    /// Pixels whose luminance exceeds `threshold` are box blurred by
+    /// `blur_radius` pixels and added back scaled by `strength`.
+    pub fn apply_bloom(&mut self, threshold: u8, blur_radius: u64, strength: f64) {
+        let mut bright = vec![0; self.pixel_buffer.len()];
+        for i in (0..self.pixel_buffer.len()).step_by(3) {
+            let color = Color {
+                r: self.pixel_buffer[i],
+                g: self.pixel_buffer[i + 1],
+                b: self.pixel_buffer[i + 2],
+                a: 0xff,
+            };
+            if color.grayscale().r > threshold {
+                bright[i..i + 3].copy_from_slice(&self.pixel_buffer[i..i + 3]);
             }
+        }
 
-            0b000_11_110 => {
-                x = 0;
-                y = 40;
+        let glow = self.box_blur(&bright, blur_radius);
+        for (pixel, glow) in self.pixel_buffer.iter_mut().zip(glow) {
+            *pixel = (*pixel as f64 + glow as f64 * strength).clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    /// Applies a separable Gaussian blur to the rendered buffer, clamping
+    /// samples at the image edges. The kernel radius is `ceil(3 * sigma)`.
+    pub fn gaussian_blur(&mut self, sigma: f64) {
+        if sigma <= 0.0 {
+            return;
+        }
+        let radius = (3.0 * sigma).ceil() as i64;
+        let weights: Vec<f64> = (-radius..=radius)
+            .map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let kernel: Vec<f64> = weights.iter().map(|w| w / total).collect();
+
+        let width = (self.width * 8 * self.sim_scale) as i64;
+        let height = (self.height * 8 * self.sim_scale) as i64;
+        let source: Vec<f64> = self.pixel_buffer.iter().map(|&c| c as f64).collect();
+
+        let mut horizontal = vec![0.0; source.len()];
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..3 {
+                    horizontal[((y * width + x) * 3 + c) as usize] = (-radius..=radius)
+                        .zip(&kernel)
+                        .map(|(d, k)| {
+                            let sx = (x + d).clamp(0, width - 1);
+                            source[((y * width + sx) * 3 + c) as usize] * k
+                        })
+                        .sum();
+                }
             }
+        }
 
-            0b000_11_111 => {
-                x = 8;
-                y = 0;
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..3 {
+                    let value: f64 = (-radius..=radius)
+                        .zip(&kernel)
+                        .map(|(d, k)| {
+                            let sy = (y + d).clamp(0, height - 1);
+                            horizontal[((sy * width + x) * 3 + c) as usize] * k
+                        })
+                        .sum();
+                    self.pixel_buffer[((y * width + x) * 3 + c) as usize] =
+                        value.round().clamp(0.0, 255.0) as u8;
+                }
             }
-            0b001_00_001 => {
-                x = 56;
-                y = 0;
+        }
+    }
+
+    /// Snaps every pixel of the rendered buffer to its nearest palette color.
+    /// An empty palette leaves the buffer unchanged.
+    pub fn quantize_to_palette(&mut self, palette: &[Color]) {
+        for pixel in self.pixel_buffer.chunks_mut(3) {
+            let color = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 0xff,
             }
-            0b001_00_010 => {
-                x = 48;
-                y = 0;
+            .nearest_in_palette(palette);
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    /// Applies ordered (4x4 Bayer) dithering to the rendered buffer to break up
+    /// banding in smooth gradients. The pattern depends only on pixel position,
+    /// so consecutive frames dither identically.
+    pub fn apply_dither(&mut self) {
+        const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+        // Spread of the dither offset, in 8-bit levels.
+        const AMPLITUDE: f64 = 4.0;
+
+        let width = (self.width * 8 * self.sim_scale) as usize;
+        for (p, pixel) in self.pixel_buffer.chunks_mut(3).enumerate() {
+            let (x, y) = (p % width, p / width);
+            let offset = ((BAYER[y % 4][x % 4] as f64 + 0.5) / 16.0 - 0.5) * AMPLITUDE;
+            for channel in pixel {
+                *channel = (*channel as f64 + offset).round().clamp(0.0, 255.0) as u8;
             }
-            0b001_00_011 => {
-                x = 48;
-                y = 0;
+        }
+    }
+
+    /// Darkens the rendered buffer toward its edges. Each pixel's brightness is
+    /// scaled from 1.0 at the center of the image down to `1 - strength` at the
+    /// corners, by its normalized distance from the center.
+    pub fn apply_vignette(&mut self, strength: f64) {
+        let width = self.width * 8 * self.sim_scale;
+        let center = Point {
+            x: width as f64 / 2.0,
+            y: (self.height * 8 * self.sim_scale) as f64 / 2.0,
+        };
+        let corner_distance = center.magnitude();
+        if corner_distance == 0.0 {
+            return;
+        }
+        for (p, pixel) in self.pixel_buffer.chunks_mut(3).enumerate() {
+            let position = Point {
+                x: (p as u64 % width) as f64 + 0.5,
+                y: (p as u64 / width) as f64 + 0.5,
+            };
+            let factor = 1.0 - strength * position.distance(&center) / corner_distance;
+            let color = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 0xff,
             }
-            0b001_00_100 => {
-                x = 56;
-                y = 0;
+            .scale_brightness(factor);
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    fn box_blur(&self, layer: &[u8], radius: u64) -> Vec<u8> {
+        let width = (self.width * 8 * self.sim_scale) as i64;
+        let height = (self.height * 8 * self.sim_scale) as i64;
+        let radius = radius as i64;
+
+        let mut horizontal = vec![0; layer.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let lo = (x - radius).max(0);
+                let hi = (x + radius).min(width - 1);
+                for c in 0..3 {
+                    let sum: u64 = (lo..=hi)
+                        .map(|sx| layer[((y * width + sx) * 3 + c) as usize] as u64)
+                        .sum();
+                    horizontal[((y * width + x) * 3 + c) as usize] =
+                        (sum / (hi - lo + 1) as u64) as u8;
+                }
             }
-            0b001_00_101 => {
-                x = 56;
-                y = 0;
+        }
+
+        let mut blurred = vec![0; layer.len()];
+        for y in 0..height {
+            let lo = (y - radius).max(0);
+            let hi = (y + radius).min(height - 1);
+            for x in 0..width {
+                for c in 0..3 {
+                    let sum: u64 = (lo..=hi)
+                        .map(|sy| horizontal[((sy * width + x) * 3 + c) as usize] as u64)
+                        .sum();
+                    blurred[((y * width + x) * 3 + c) as usize] =
+                        (sum / (hi - lo + 1) as u64) as u8;
+                }
             }
-            0b001_00_110 => {
-                x = 48;
-                y = 0;
+        }
+        blurred
+    }
+
+    /// Blends an RGB buffer of the same size on top of the pixel buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` is not the same length as the pixel buffer.
+    pub fn blend_pixel_buffer(&mut self, other: &[u8], mode: BlendMode) {
+        assert_eq!(
+            self.pixel_buffer.len(),
+            other.len(),
+            "pixel buffers must have matching dimensions"
+        );
+        for (pixel, top) in self.pixel_buffer.chunks_mut(3).zip(other.chunks(3)) {
+            let base = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 0xff,
+            };
+            let top = Color {
+                r: top[0],
+                g: top[1],
+                b: top[2],
+                a: 0xff,
+            };
+            let blended = base.blend_with_mode(top, mode);
+            pixel[0] = blended.r;
+            pixel[1] = blended.g;
+            pixel[2] = blended.b;
+        }
+    }
+
+    /// Composites another map's rendered pixel buffer on top of this one.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::DimensionMismatch` if the two renders are not the same size,
+    /// in which case this map is left unchanged.
+    pub fn composite_over(&mut self, other: &Map, mode: BlendMode) -> Result<(), MapError> {
+        self.check_same_size(other)?;
+        self.blend_pixel_buffer(&other.pixel_buffer, mode);
+        Ok(())
+    }
+
+    /// Cross-fades this map's rendered pixel buffer `t` of the way toward
+    /// another map's, for transitions between frames.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::DimensionMismatch` if the two renders are not the same size,
+    /// in which case this map is left unchanged.
+    pub fn lerp_with(&mut self, other: &Map, t: f64) -> Result<(), MapError> {
+        self.check_same_size(other)?;
+        for (pixel, target) in self
+            .pixel_buffer
+            .chunks_mut(3)
+            .zip(other.pixel_buffer.chunks(3))
+        {
+            let from = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 0xff,
+            };
+            let to = Color {
+                r: target[0],
+                g: target[1],
+                b: target[2],
+                a: 0xff,
+            };
+            let mixed = from.interpolate(to, t);
+            pixel.copy_from_slice(&[mixed.r, mixed.g, mixed.b]);
+        }
+        Ok(())
+    }
+
+    /// Saves the pixel buffer as an RGB PNG, writing the bytes unchanged. PNG
+    /// viewers treat them as sRGB; see [`Map::save_linear`] for buffers holding
+    /// linear-light values.
+    pub fn save(&self, path: &str) {
+        self.save_with(path, SaveOptions::default()).unwrap();
+    }
+
+    /// Saves the pixel buffer with the given format, scale and channels.
+    ///
+    /// # Returns
+    ///
+    /// An error if the file cannot be created or the image cannot be encoded.
+    pub fn save_with(&self, path: &str, opts: SaveOptions) -> Result<(), MapError> {
+        let width = self.width * 8 * self.sim_scale;
+        let height = self.height * 8 * self.sim_scale;
+        let rgba = opts.rgba && opts.format == ImageFormat::Png;
+        let source: Cow<[u8]> = if rgba {
+            Cow::Owned(
+                self.pixel_buffer
+                    .chunks(3)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xff])
+                    .collect(),
+            )
+        } else {
+            Cow::Borrowed(&self.pixel_buffer)
+        };
+        let channels = if rgba { 4 } else { 3 };
+        let data = upscale(&source, width, height, channels, opts.upscale);
+        let (width, height) = (width * opts.upscale, height * opts.upscale);
+        match opts.format {
+            ImageFormat::Png => {
+                let color = if rgba {
+                    png::ColorType::Rgba
+                } else {
+                    png::ColorType::Rgb
+                };
+                try_write_png(path, width as u32, height as u32, color, &data)
             }
-            0b001_00_111 => {
+            ImageFormat::Ppm => {
+                let mut file = File::create(path)?;
+                write!(file, "P6\n{width} {height}\n255\n")?;
+                file.write_all(&data)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Saves the pixel buffer as an RGB PNG, treating its values as linear
+    /// light and applying the sRGB transfer function so they display with the
+    /// intended brightness.
+    pub fn save_linear(&self, path: &str) {
+        let encoded: Vec<u8> = self
+            .pixel_buffer
+            .iter()
+            .map(|&channel| {
+                let c = channel as f64 / 255.0;
+                let srgb = if c <= 0.0031308 {
+                    c * 12.92
+                } else {
+                    1.055 * c.powf(1.0 / 2.4) - 0.055
+                };
+                (srgb * 255.0).round() as u8
+            })
+            .collect();
+        write_png(
+            path,
+            (self.width * 8 * self.sim_scale) as u32,
+            (self.height * 8 * self.sim_scale) as u32,
+            png::ColorType::Rgb,
+            &encoded,
+        );
+    }
+
+    pub fn save_upscaled(&self, path: &str, scale: u64) {
+        let opts = SaveOptions {
+            upscale: scale,
+            ..SaveOptions::default()
+        };
+        self.save_with(path, opts).unwrap();
+    }
+
+    /// Saves a `view_w` by `view_h` pixel window of the rendered buffer,
+    /// centered on the world point `center`, as an RGB PNG.
+    ///
+    /// The window is shifted to stay inside the image near its edges, and is
+    /// clipped to the image size if it is larger than the whole render.
+    pub fn save_view(
+        &self,
+        center: Point,
+        view_w: u64,
+        view_h: u64,
+        path: &str,
+    ) -> Result<(), MapError> {
+        let width = self.width * 8 * self.sim_scale;
+        let height = self.height * 8 * self.sim_scale;
+        let (view_w, view_h) = (view_w.min(width), view_h.min(height));
+        let scale = 8.0 * self.sim_scale as f64;
+        let left = (center.x * scale - view_w as f64 / 2.0)
+            .round()
+            .clamp(0.0, (width - view_w) as f64) as u64;
+        let top = (center.y * scale - view_h as f64 / 2.0)
+            .round()
+            .clamp(0.0, (height - view_h) as f64) as u64;
+
+        let mut view = Vec::with_capacity((view_w * view_h * 3) as usize);
+        for y in top..top + view_h {
+            let start = ((y * width + left) * 3) as usize;
+            view.extend_from_slice(&self.pixel_buffer[start..start + view_w as usize * 3]);
+        }
+        try_write_png(
+            path,
+            view_w as u32,
+            view_h as u32,
+            png::ColorType::Rgb,
+            &view,
+        )
+    }
+
+    /// Counts the pixels that differ between this map's render and another's.
+    ///
+    /// # Returns
+    ///
+    /// The number of differing pixels, or `MapError::DimensionMismatch` if the
+    /// pixel buffers are not the same size.
+    pub fn diff_count(&self, other: &Map) -> Result<u64, MapError> {
+        self.check_same_size(other)?;
+        Ok(self
+            .pixel_buffer
+            .chunks(3)
+            .zip(other.pixel_buffer.chunks(3))
+            .filter(|(a, b)| a != b)
+            .count() as u64)
+    }
+
+    /// Highlights the pixels that differ between this map's render and another's.
+    ///
+    /// # Returns
+    ///
+    /// An RGB buffer that is red where the pixels differ and black elsewhere,
+    /// or `MapError::DimensionMismatch` if the pixel buffers are not the same
+    /// size.
+    pub fn diff_mask(&self, other: &Map) -> Result<Vec<u8>, MapError> {
+        self.check_same_size(other)?;
+        Ok(self
+            .pixel_buffer
+            .chunks(3)
+            .zip(other.pixel_buffer.chunks(3))
+            .flat_map(|(a, b)| if a != b { [0xff, 0, 0] } else { [0, 0, 0] })
+            .collect())
+    }
+
+    fn check_same_size(&self, other: &Map) -> Result<(), MapError> {
+        let size = |map: &Map| {
+            (
+                map.width * 8 * map.sim_scale,
+                map.height * 8 * map.sim_scale,
+            )
+        };
+        if size(self) != size(other) || self.pixel_buffer.len() != other.pixel_buffer.len() {
+            return Err(MapError::DimensionMismatch {
+                what: "pixel buffer",
+                expected: self.pixel_buffer.len(),
+                actual: other.pixel_buffer.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Converts a world position, measured in squares, to the output pixel
+    /// containing it. Negative coordinates saturate to 0.
+    pub fn world_to_screen(&self, p: Point) -> (u64, u64) {
+        let scale = 8.0 * self.sim_scale as f64;
+        let to_pixel = |world: f64| {
+            let scaled = world * scale;
+            // Positions from `screen_to_world` can land a rounding error short
+            // of the pixel edge they started on.
+            let edge = scaled.round();
+            if (scaled - edge).abs() < 1e-9 {
+                edge as u64
+            } else {
+                scaled.floor() as u64
+            }
+        };
+        (to_pixel(p.x), to_pixel(p.y))
+    }
+
+    /// Converts an output pixel to the world position of its top-left corner,
+    /// measured in squares.
+    pub fn screen_to_world(&self, x: u64, y: u64) -> Point {
+        Point {
+            x: x as f64 / 8. / self.sim_scale as f64,
+            y: y as f64 / 8. / self.sim_scale as f64,
+        }
+    }
+
+    /// Iterates over the rendered pixel buffer in row-major order.
+    ///
+    /// # Returns
+    ///
+    /// An iterator of `(x, y, color)` in output pixel coordinates. The colors
+    /// are always opaque since the buffer stores RGB only.
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (u64, u64, Color)> + '_ {
+        let width = self.width * 8 * self.sim_scale;
+        self.pixel_buffer
+            .chunks_exact(3)
+            .enumerate()
+            .map(move |(i, pixel)| {
+                let color = Color {
+                    r: pixel[0],
+                    g: pixel[1],
+                    b: pixel[2],
+                    a: 0xff,
+                };
+                (i as u64 % width, i as u64 / width, color)
+            })
+    }
+
+    /// Runs `f` on every rendered pixel with the world position of the pixel's
+    /// top-left corner, writing back any changes to its color. The color is
+    /// passed opaque and changes to its alpha are discarded.
+    pub fn for_each_world_pixel<F: FnMut(Point, &mut Color)>(&mut self, mut f: F) {
+        let width = self.width * 8 * self.sim_scale;
+        for p in 0..self.pixel_buffer.len() / 3 {
+            let point = self.screen_to_world(p as u64 % width, p as u64 / width);
+            let pixel = &mut self.pixel_buffer[p * 3..p * 3 + 3];
+            let mut color = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 0xff,
+            };
+            f(point, &mut color);
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    /// Replaces the pixel buffer with colors given in row-major order, the
+    /// inverse of [`Map::iter_pixels`]. Alpha is dropped.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::DimensionMismatch` if `colors` does not hold exactly one color
+    /// per output pixel.
+    pub fn set_pixel_colors(&mut self, colors: &[Color]) -> Result<(), MapError> {
+        let expected =
+            (self.height * 8 * self.sim_scale * self.width * 8 * self.sim_scale) as usize;
+        if colors.len() != expected {
+            return Err(MapError::DimensionMismatch {
+                what: "colors",
+                expected,
+                actual: colors.len(),
+            });
+        }
+        for (pixel, color) in self.pixel_buffer.chunks_exact_mut(3).zip(colors) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        Ok(())
+    }
+
+    /// Draws the outline of a circle into the pixel buffer using the midpoint
+    /// circle algorithm. `center` and `radius` are in output pixel space; pixels
+    /// outside the buffer are clipped.
+    pub fn draw_circle(&mut self, center: Point, radius: f64, color: Color) {
+        let cx = center.x.round() as i64;
+        let cy = center.y.round() as i64;
+        let mut x = radius.round() as i64;
+        let mut y = 0;
+        let mut err = 1 - x;
+        while x >= y {
+            for (dx, dy) in [
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                self.set_pixel(cx + dx, cy + dy, color);
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Fills a circle in the pixel buffer. A pixel is filled when its center lies
+    /// within `radius` of `center`; pixels outside the buffer are clipped.
+    pub fn fill_circle(&mut self, center: Point, radius: f64, color: Color) {
+        let min_x = (center.x - radius).floor() as i64;
+        let max_x = (center.x + radius).ceil() as i64;
+        let min_y = (center.y - radius).floor() as i64;
+        let max_y = (center.y + radius).ceil() as i64;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let pixel = Point {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                };
+                if pixel.distance(&center) <= radius {
+                    self.set_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Stamps a filled circle of `radius` output pixels at every light's
+    /// position, for debugging light placement on top of a render.
+    pub fn draw_light_markers(&mut self, radius: f64, color: Color) {
+        let scale = 8.0 * self.sim_scale as f64;
+        for position in self.light_positions() {
+            self.fill_circle(position * scale, radius, color);
+        }
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: Color) {
+        let width = (self.width * 8 * self.sim_scale) as i64;
+        let height = (self.height * 8 * self.sim_scale) as i64;
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return;
+        }
+        let i = ((y * width + x) * 3) as usize;
+        self.pixel_buffer[i] = color.r;
+        self.pixel_buffer[i + 1] = color.g;
+        self.pixel_buffer[i + 2] = color.b;
+    }
+
+    #[inline]
+    fn is_within_square(&self, point: &Point) -> bool {
+        if self.wrap_edges {
+            return self.square_at(point.x.floor() as i64, point.y.floor() as i64);
+        }
+        let grid_x = (point.x) as usize;
+        let grid_y = (point.y) as usize;
+        if grid_x < self.width as usize && grid_y < self.height as usize {
+            self.squares[grid_y][grid_x]
+        } else {
+            false
+        }
+    }
+
+    /// Looks up a grid square, wrapping around the edges when `wrap_edges` is
+    /// set and treating squares off the grid as open otherwise.
+    fn square_at(&self, x: i64, y: i64) -> bool {
+        let (width, height) = (self.width as i64, self.height as i64);
+        if width == 0 || height == 0 {
+            return false;
+        }
+        let (x, y) = if self.wrap_edges {
+            (x.rem_euclid(width), y.rem_euclid(height))
+        } else if x < 0 || y < 0 || x >= width || y >= height {
+            return false;
+        } else {
+            (x, y)
+        };
+        self.squares[y as usize][x as usize]
+    }
+
+    fn get_root_square(&self, point: &Point) -> Point {
+        Point {
+            x: ((point.x * self.tile_width as f64) % self.tile_width as f64).floor(),
+            y: ((point.y * self.tile_height as f64) % self.tile_height as f64).floor(),
+        }
+    }
+
+    /// Builds a per-square index of the enabled lights that could illuminate
+    /// that square: those whose reach overlaps it and whose flood fill reaches
+    /// it. Indices within each bucket stay in light order so blending is
+    /// unaffected.
+    fn light_buckets(&self) -> Vec<Vec<usize>> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut buckets = vec![Vec::new(); width * height];
+        if width == 0 || height == 0 {
+            return buckets;
+        }
+        for (index, light) in self.lights.iter().enumerate() {
+            if !light.enabled {
+                continue;
+            }
+            let reach = if light.casts_shadows {
+                self.light_reachability(light)
+            } else {
+                None
+            };
+            let (pos, radius) = (light.position, light.intensity);
+            let min_x = (pos.x - radius).floor().max(0.0) as usize;
+            let min_y = (pos.y - radius).floor().max(0.0) as usize;
+            let max_x = ((pos.x + radius).floor() as i64).min(width as i64 - 1);
+            let max_y = ((pos.y + radius).floor() as i64).min(height as i64 - 1);
+            if max_x < 0 || max_y < 0 {
+                continue;
+            }
+            for y in min_y..=max_y as usize {
+                for x in min_x..=max_x as usize {
+                    if reach.as_ref().is_none_or(|reach| reach[y][x]) {
+                        buckets[y * width + x].push(index);
+                    }
+                }
+            }
+        }
+        buckets
+    }
+
+    /// Flood fills open squares outward from the square containing `light`.
+    ///
+    /// Neighbours are 8-connected since a sampled LOS ray can slip diagonally
+    /// between two walls, so any square the light can see is marked reachable.
+    /// Returns `None` when the light is outside the grid, where rays may travel
+    /// around the map and no squares can be ruled out.
+    fn light_reachability(&self, light: &Light) -> Option<Vec<Vec<bool>>> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut reach = vec![vec![false; width]; height];
+        let start = &light.position;
+        if start.x < 0.0 || start.y < 0.0 || start.x >= width as f64 || start.y >= height as f64 {
+            return None;
+        }
+        let (start_x, start_y) = (start.x as usize, start.y as usize);
+        if self.squares[start_y][start_x] {
+            return Some(reach);
+        }
+
+        reach[start_y][start_x] = true;
+        let mut stack = vec![(start_x, start_y)];
+        while let Some((x, y)) = stack.pop() {
+            let neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)));
+            for (dx, dy) in neighbours {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let (nx, ny) = (nx as usize, ny as usize);
+                if !reach[ny][nx] && !self.squares[ny][nx] {
+                    reach[ny][nx] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        Some(reach)
+    }
+
+    /// Returns a point just outside the edge of the grid square nearest to
+    /// `point`, i.e. on the open side of the wall face that `point` belongs to.
+    fn nearest_wall_face(&self, point: &Point) -> Point {
+        const NUDGE: f64 = 1e-6;
+        let left = point.x - point.x.floor();
+        let top = point.y - point.y.floor();
+        let right = 1.0 - left;
+        let bottom = 1.0 - top;
+        let nearest = left.min(right).min(top).min(bottom);
+        if nearest == left {
+            Point::new(point.x.floor() - NUDGE, point.y)
+        } else if nearest == right {
+            Point::new(point.x.floor() + 1.0 + NUDGE, point.y)
+        } else if nearest == top {
+            Point::new(point.x, point.y.floor() - NUDGE)
+        } else {
+            Point::new(point.x, point.y.floor() + 1.0 + NUDGE)
+        }
+    }
+
+    /// Returns the fraction of LOS samples between `a` and `b` that fall in
+    /// open squares.
+    fn open_fraction(&self, a: &Point, b: &Point) -> f64 {
+        let steps = a.distance(b).ceil() as usize * 20;
+        if steps == 0 {
+            return 1.0;
+        }
+        let open = (0..steps)
+            .filter(|&i| {
+                let t = i as f64 / steps as f64;
+                !self.is_within_square(&(*a + (*b - *a) * t))
+            })
+            .count();
+        open as f64 / steps as f64
+    }
+
+    /// Whether `light` shines on `point`, ignoring walls for lights that cast
+    /// no shadows.
+    fn light_visible(&self, light: &Light, point: &Point) -> bool {
+        !light.casts_shadows || self.point_has_los(&light.position, point)
+    }
+
+    fn point_has_los(&self, a: &Point, b: &Point) -> bool {
+        let mut previous = None;
+        for point in self.los_samples(a, b) {
+            if self.los_blocked(previous.as_ref(), &point) {
+                return false;
+            }
+            previous = Some(point);
+        }
+        true
+    }
+
+    /// Lists the points a line-of-sight test from `a` to `b` samples, for
+    /// debugging unexpected shadows.
+    ///
+    /// # Returns
+    ///
+    /// Each sampled point paired with whether it blocks the ray, ending at the
+    /// first blocking sample. A sample blocks if it lies in a solid square or,
+    /// with `corner_leak_fix`, squeezes between two diagonal walls. An empty
+    /// list means `b` is within `shadow_bias` of `a` and is always visible.
+    pub fn trace_los(&self, a: Point, b: Point) -> Vec<(Point, bool)> {
+        let mut trace: Vec<(Point, bool)> = Vec::new();
+        for point in self.los_samples(&a, &b) {
+            let previous = trace.last().map(|(previous, _)| previous);
+            let solid = self.los_blocked(previous, &point);
+            trace.push((point, solid));
+            if solid {
+                break;
+            }
+        }
+        trace
+    }
+
+    /// Whether the LOS sample `point`, reached from the sample `previous`,
+    /// blocks the ray.
+    fn los_blocked(&self, previous: Option<&Point>, point: &Point) -> bool {
+        if self.is_within_square(point) {
+            return true;
+        }
+        let Some(previous) = previous.filter(|_| self.corner_leak_fix) else {
+            return false;
+        };
+        let (x, y) = (point.x.floor() as i64, point.y.floor() as i64);
+        let (px, py) = (previous.x.floor() as i64, previous.y.floor() as i64);
+        x != px && y != py && self.square_at(x, py) && self.square_at(px, y)
+    }
+
+    fn los_samples(&self, a: &Point, b: &Point) -> impl Iterator<Item = Point> {
+        // Pull the end of the ray back toward the light by `shadow_bias` so a
+        // pixel hugging a wall edge isn't occluded by the cell it borders.
+        let a = *a;
+        let length = a.distance(b);
+        let b = *b - (*b - a) * (self.shadow_bias / length);
+
+        let dx = b.x - a.x;
+        let dy = b.y - a.y;
+        let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+
+        let steps = if length <= self.shadow_bias {
+            0
+        } else {
+            distance.ceil() as usize * 20
+        };
+        let dx = dx / steps as f64;
+        let dy = dy / steps as f64;
+
+        (0..steps).map(move |i| Point {
+            x: a.x + dx * i as f64,
+            y: a.y + dy * i as f64,
+        })
+    }
+
+    fn get_surrounding_square_bitmap(&self, point: &Point) -> u8 {
+        const NEIGHBOURS: [(i64, i64, u8); 8] = [
+            (-1, -1, 0b1000_0000),
+            (0, -1, 0b0100_0000),
+            (1, -1, 0b0010_0000),
+            (-1, 0, 0b0001_0000),
+            (1, 0, 0b0000_1000),
+            (-1, 1, 0b0000_0100),
+            (0, 1, 0b0000_0010),
+            (1, 1, 0b0000_0001),
+        ];
+
+        let grid_x = point.x as i64;
+        let grid_y = point.y as i64;
+
+        NEIGHBOURS
+            .iter()
+            .filter(|(dx, dy, _)| self.square_at(grid_x + dx, grid_y + dy))
+            .fold(0, |bitmap, (_, _, bit)| bitmap | bit)
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    fn get_tex_cord(&self, point: &Point, bitmap: u8) -> (u32, u32) {
+        let x: u32;
+        let y: u32;
+        let root_square = self.get_root_square(point);
+
+        // println!("{:?}", root_square);
+
+        match bitmap {
+            0b000_00_000 => {
+                x = 56;
+                y = 0;
+            }
+            0b100_00_000 => {
+                x = 56;
+                y = 0;
+            }
+            0b010_00_000 => {
                 x = 48;
+                y = 16;
+            }
+            0b001_00_000 => {
+                x = 56;
                 y = 0;
             }
-            0b001_01_000 => {
+            0b000_10_000 => {
+                x = 16;
+                y = 24;
+            }
+            0b000_01_000 => {
                 x = 0;
                 y = 24;
             }
-            0b001_01_001 => {
+            0b000_00_100 => {
+                x = 56;
+                y = 0;
+            }
+            0b000_00_010 => {
+                x = 48;
+                y = 0;
+            }
+            0b000_00_001 => {
+                x = 56;
+                y = 0;
+            }
+            0b000_00_011 => {
+                x = 48;
+                y = 0;
+            }
+            0b000_00_101 => {
+                x = 56;
+                y = 0;
+            }
+            0b000_00_110 => {
+                x = 48;
+                y = 0;
+            }
+            0b000_00_111 => {
+                x = 48;
+                y = 0;
+            }
+            0b000_01_001 => {
                 x = 0;
                 y = 24;
             }
-            0b001_01_010 => {
+            0b000_01_010 => {
                 x = 56;
                 y = 16;
             }
-            0b001_01_011 => {
+            0b000_01_011 => {
                 x = 0;
                 y = 0;
             }
-            0b001_01_100 => {
+            0b000_01_100 => {
                 x = 0;
                 y = 24;
             }
-            0b001_01_101 => {
+            0b000_01_101 => {
                 x = 0;
                 y = 24;
             }
-            0b001_01_110 => {
+            0b000_01_110 => {
                 x = 56;
                 y = 16;
             }
-            0b001_01_111 => {
+            0b000_01_111 => {
                 x = 0;
                 y = 0;
             }
-            0b001_10_000 => {
-                x = 16;
-                y = 24;
-            }
-            0b001_10_001 => {
+            0b000_10_001 => {
                 x = 16;
                 y = 24;
             }
-            0b001_10_010 => {
+            0b000_10_010 => {
                 x = 56;
                 y = 8;
             }
-            0b001_10_011 => {
+            0b000_10_011 => {
                 x = 56;
                 y = 8;
             }
-            0b001_10_100 => {
+            0b000_10_100 => {
                 x = 16;
                 y = 24;
             }
-            0b001_10_101 => {
+            0b000_10_101 => {
                 x = 16;
                 y = 24;
             }
-            0b001_10_110 => {
+            0b000_10_110 => {
                 x = 16;
                 y = 0;
             }
-            0b001_10_111 => {
+            0b000_10_111 => {
                 x = 16;
                 y = 0;
             }
-            0b001_11_000 => {
+            0b000_11_000 => {
                 x = 8;
                 y = 24;
             }
-            0b001_11_001 => {
+            0b000_11_001 => {
+                x = 8;
+                y = 24;
+            }
+
+            0b000_11_010 => {
+                x = 8;
+                y = 40;
+            }
+
+            0b000_11_011 => {
+                x = 16;
+                y = 40;
+            }
+
+            0b000_11_100 => {
+                x = 8;
+                y = 24;
+            }
+
+            0b000_11_101 => {
+                x = 8;
+                y = 24;
+            }
+
+            0b000_11_110 => {
+                x = 0;
+                y = 40;
+            }
+
+            0b000_11_111 => {
+                x = 8;
+                y = 0;
+            }
+            0b001_00_001 => {
+                x = 56;
+                y = 0;
+            }
+            0b001_00_010 => {
+                x = 48;
+                y = 0;
+            }
+            0b001_00_011 => {
+                x = 48;
+                y = 0;
+            }
+            0b001_00_100 => {
+                x = 56;
+                y = 0;
+            }
+            0b001_00_101 => {
+                x = 56;
+                y = 0;
+            }
+            0b001_00_110 => {
+                x = 48;
+                y = 0;
+            }
+            0b001_00_111 => {
+                x = 48;
+                y = 0;
+            }
+            0b001_01_000 => {
+                x = 0;
+                y = 24;
+            }
+            0b001_01_001 => {
+                x = 0;
+                y = 24;
+            }
+            0b001_01_010 => {
+                x = 56;
+                y = 16;
+            }
+            0b001_01_011 => {
+                x = 0;
+                y = 0;
+            }
+            0b001_01_100 => {
+                x = 0;
+                y = 24;
+            }
+            0b001_01_101 => {
+                x = 0;
+                y = 24;
+            }
+            0b001_01_110 => {
+                x = 56;
+                y = 16;
+            }
+            0b001_01_111 => {
+                x = 0;
+                y = 0;
+            }
+            0b001_10_000 => {
+                x = 16;
+                y = 24;
+            }
+            0b001_10_001 => {
+                x = 16;
+                y = 24;
+            }
+            0b001_10_010 => {
+                x = 56;
+                y = 8;
+            }
+            0b001_10_011 => {
+                x = 56;
+                y = 8;
+            }
+            0b001_10_100 => {
+                x = 16;
+                y = 24;
+            }
+            0b001_10_101 => {
+                x = 16;
+                y = 24;
+            }
+            0b001_10_110 => {
+                x = 16;
+                y = 0;
+            }
+            0b001_10_111 => {
+                x = 16;
+                y = 0;
+            }
+            0b001_11_000 => {
+                x = 8;
+                y = 24;
+            }
+            0b001_11_001 => {
                 x = 8;
                 y = 24;
             }
@@ -1384,6 +3398,1502 @@ impl Map {
 
         // println!("{:?}", point);
 
-        (x + root_square.x as u32, y + root_square.y as u32)
+        // The table lists tile origins for an atlas of 8x8 tiles.
+        (
+            x / 8 * self.tile_width + root_square.x as u32,
+            y / 8 * self.tile_height + root_square.y as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color {
+        r: 0xff,
+        g: 0xff,
+        b: 0xff,
+        a: 0xff,
+    };
+
+    fn test_map(width: u64, height: u64) -> Map {
+        Map::new(height, width, 1, "texture-base.png".to_string(), 0.1, 1.0)
+    }
+
+    fn white_light(x: f64, y: f64, intensity: f64) -> Light {
+        Light::new(Point::new(x, y), WHITE, intensity)
+    }
+
+    #[test]
+    fn lights_mut_edits_lights_in_place() {
+        let mut map = test_map(4, 4);
+        map.add_light(white_light(1.0, 1.0, 2.0));
+        map.add_light(white_light(2.0, 2.0, 3.0));
+        for light in map.lights_mut() {
+            light.intensity *= 2.0;
+        }
+        let intensities: Vec<f64> = map.lights().iter().map(|light| light.intensity).collect();
+        assert_eq!(intensities, [4.0, 6.0]);
+    }
+
+    fn pixel(map: &Map, x: u64, y: u64) -> [u8; 3] {
+        let i = ((y * map.width * 8 * map.sim_scale + x) * 3) as usize;
+        [
+            map.pixel_buffer[i],
+            map.pixel_buffer[i + 1],
+            map.pixel_buffer[i + 2],
+        ]
+    }
+
+    #[test]
+    fn circles_outline_fill_and_clip() {
+        let mut map = test_map(4, 4);
+        map.draw_circle(Point::new(16.0, 16.0), 5.0, WHITE);
+        assert_eq!(pixel(&map, 21, 16), [0xff; 3]);
+        assert_eq!(pixel(&map, 16, 11), [0xff; 3]);
+        assert_eq!(pixel(&map, 16, 16), [0; 3]);
+
+        // Pixel centers are tested, so a disc on a pixel corner is symmetric.
+        map.fill_circle(Point::new(16.0, 16.0), 3.0, WHITE);
+        assert_eq!(pixel(&map, 16, 16), [0xff; 3]);
+        assert_eq!(pixel(&map, 18, 16), [0xff; 3]);
+        assert_eq!(pixel(&map, 13, 16), [0xff; 3]);
+        assert_eq!(pixel(&map, 19, 15), [0; 3]);
+        assert_eq!(pixel(&map, 12, 15), [0; 3]);
+        assert_eq!(pixel(&map, 18, 18), [0; 3]);
+
+        map.fill_circle(Point::new(-4.0, 40.0), 100.0, WHITE);
+        assert!(map.pixel_buffer.iter().all(|&channel| channel == 0xff));
+    }
+
+    /// A map ringed by walls with one warm light in the middle.
+    fn walled_map(width: u64, height: u64) -> Map {
+        let mut map = test_map(width, height);
+        map.fill_border_walls();
+        let center = Point::new(width as f64 / 2.0, height as f64 / 2.0);
+        let warm = Color {
+            r: 0xff,
+            g: 0xa0,
+            b: 0x40,
+            a: 0xff,
+        };
+        map.add_light(Light::new(center, warm, width.max(height) as f64 / 2.0));
+        map
+    }
+
+    #[test]
+    fn render_stats_counts_the_work_done() {
+        let mut map = walled_map(4, 4);
+        let stats = map.render_stats();
+        assert_eq!(stats.pixels, 32 * 32);
+        assert!(stats.lit_pixels > 0 && stats.lit_pixels < stats.pixels);
+        assert!(stats.los_checks <= stats.light_evaluations);
+        assert_eq!(map.render_stats().lit_pixels, stats.lit_pixels);
+
+        let mut dark = test_map(4, 4);
+        let stats = dark.render_stats();
+        assert_eq!((stats.light_evaluations, stats.lit_pixels), (0, 0));
+    }
+
+    #[test]
+    fn more_lights_need_more_los_checks() {
+        let mut map = walled_map(4, 4);
+        let single = map.render_stats();
+        map.add_lights([white_light(1.5, 1.5, 2.0), white_light(2.5, 1.5, 2.0)]);
+        let triple = map.render_stats();
+        assert!(triple.los_checks > single.los_checks);
+        assert!(triple.light_evaluations > single.light_evaluations);
+    }
+
+    #[test]
+    fn shadow_bias_trims_the_lit_end_of_the_ray() {
+        let mut map = test_map(4, 4);
+        map.squares[0][1] = true;
+        let light = Point::new(0.5, 0.5);
+        let just_inside_wall = Point::new(1.04, 0.5);
+        assert!(map.point_has_los(&light, &just_inside_wall));
+        assert!(map.point_has_los(&Point::new(1.5, 0.5), &Point::new(1.52, 0.5)));
+        map.shadow_bias = 0.0;
+        assert!(!map.point_has_los(&light, &just_inside_wall));
+    }
+
+    #[test]
+    fn merge_layer_blends_by_texel_opacity() {
+        let mut buffer = vec![100; 9];
+        let layer = [200, 0, 0, 0xff, 200, 0, 0, 0, 200, 0, 0, 128];
+        merge_layer(&mut buffer, &layer);
+        assert_eq!(buffer, [200, 0, 0, 100, 100, 100, 150, 49, 49]);
+    }
+
+    #[test]
+    fn composite_layers_is_repeatable_and_leaves_the_cache_clean() {
+        let mut map = walled_map(6, 6);
+        map.color_floor(1.0);
+        let upper = map.add_layer();
+        map.set_active_layer(upper);
+        map.squares[2][2] = true;
+        map.set_active_layer(0);
+
+        map.composite_layers();
+        let composite = map.pixel_buffer.clone();
+        map.composite_layers();
+        assert_eq!(map.pixel_buffer, composite);
+
+        let mut single = walled_map(6, 6);
+        single.color_floor(1.0);
+        single.render();
+        assert_ne!(composite, single.pixel_buffer);
+        map.render();
+        assert_eq!(map.pixel_buffer, single.pixel_buffer);
+    }
+
+    #[test]
+    fn bloom_spreads_only_bright_pixels() {
+        let mut map = test_map(2, 2);
+        map.set_pixel(8, 8, WHITE);
+        map.apply_bloom(200, 1, 1.0);
+        assert_eq!(pixel(&map, 8, 8), [0xff; 3]);
+        assert_eq!(pixel(&map, 9, 9), [28; 3]);
+        assert_eq!(pixel(&map, 10, 8), [0; 3]);
+
+        let mut dim = test_map(2, 2);
+        let gray = WHITE.scale_brightness(0.5);
+        dim.set_pixel(8, 8, gray);
+        dim.apply_bloom(200, 1, 1.0);
+        assert_eq!(pixel(&dim, 8, 8), [128; 3]);
+        assert_eq!(pixel(&dim, 9, 8), [0; 3]);
+    }
+
+    #[test]
+    fn blend_pixel_buffer_applies_the_mode_per_pixel() {
+        let mut map = test_map(1, 1);
+        map.pixel_buffer.fill(128);
+        let top: Vec<u8> = [0xff, 0, 128].repeat(64);
+        map.blend_pixel_buffer(&top, BlendMode::Multiply);
+        assert!(map
+            .pixel_buffer
+            .chunks(3)
+            .all(|pixel| pixel == [128, 0, 64]));
+    }
+
+    #[test]
+    #[should_panic(expected = "matching dimensions")]
+    fn blend_pixel_buffer_rejects_other_sizes() {
+        let mut map = test_map(1, 1);
+        map.blend_pixel_buffer(&[0; 3], BlendMode::Normal);
+    }
+
+    #[test]
+    fn validate_reports_the_first_inconsistency() {
+        let mut map = walled_map(4, 4);
+        assert!(map.validate().is_ok());
+
+        map.add_light(white_light(4.5, 1.0, 1.0));
+        assert!(matches!(
+            map.validate(),
+            Err(MapError::LightOutOfBounds { index: 1, .. })
+        ));
+        map.lights.pop();
+
+        map.squares[2].pop();
+        assert!(matches!(
+            map.validate(),
+            Err(MapError::DimensionMismatch {
+                what: "square columns",
+                expected: 4,
+                actual: 3,
+            })
+        ));
+        map.squares[2].push(false);
+
+        map.pixel_buffer.pop();
+        assert!(matches!(
+            map.validate(),
+            Err(MapError::DimensionMismatch { .. })
+        ));
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("shader_test_{name}"));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn set_normal_map_decodes_rgb_and_checks_the_size() {
+        let mut map = walled_map(4, 4);
+        let (width, height) = (map.texture_width, map.texture_height);
+        let path = temp_path("normal_map.png");
+        // Every normal points along +x, so only walls left of the light face it.
+        let normals = [0xff, 128, 0].repeat((width * height) as usize);
+        try_write_png(&path, width, height, png::ColorType::Rgb, &normals).unwrap();
+        map.set_normal_map(&path).unwrap();
+        assert_eq!(
+            map.normal_map.as_ref().map(Vec::len),
+            Some((width * height * 4) as usize)
+        );
+        map.render();
+        let mut plain = walled_map(4, 4);
+        plain.render();
+        assert_ne!(pixel(&map, 7, 16), pixel(&plain, 7, 16));
+        assert_eq!(pixel(&map, 24, 16), pixel(&plain, 24, 16));
+
+        try_write_png(&path, 3, 3, png::ColorType::Rgb, &[0; 27]).unwrap();
+        assert!(matches!(
+            map.set_normal_map(&path),
+            Err(MapError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            map.set_normal_map("missing.png"),
+            Err(MapError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn light_walls_tints_only_wall_pixels() {
+        let mut plain = walled_map(4, 4);
+        plain.render();
+        let mut tinted = walled_map(4, 4);
+        tinted.set_light_walls(true);
+        tinted.render();
+
+        let mut walls_changed = 0;
+        for (x, y, color) in tinted.iter_pixels() {
+            let same = color.channels()[..3] == pixel(&plain, x, y);
+            if tinted.is_within_square(&tinted.screen_to_world(x, y)) {
+                walls_changed += !same as u32;
+            } else {
+                assert!(same);
+            }
+        }
+        assert!(walls_changed > 0);
+    }
+
+    #[test]
+    fn light_at_and_lit_squares_respect_reach_and_walls() {
+        let mut map = walled_map(8, 8);
+        map.squares[4][5] = true;
+        assert_eq!(map.light_at(&Point::new(4.0, 4.0)), 1.0);
+        assert!((map.light_at(&Point::new(2.0, 4.0)) - 0.5).abs() < 1e-9);
+        assert_eq!(map.light_at(&Point::new(0.5, 0.5)), 0.0);
+
+        let lit = map.lit_squares(0.0);
+        assert!(lit[4][4] && lit[3][5]);
+        assert!(!lit[4][5], "wall squares are never lit");
+        assert!(!lit[4][6], "the square behind the wall is shadowed");
+        assert!(!lit[0][0]);
+        assert!(!map.lit_squares(0.9)[3][3]);
+    }
+
+    #[test]
+    fn cast_ray_stops_at_the_first_wall() {
+        let mut map = test_map(8, 4);
+        map.squares[1][5] = true;
+
+        let hit = map.cast_ray(Point::new(1.5, 1.5), 0.0, 10.0).unwrap();
+        assert_eq!(hit.square, (5, 1));
+        assert!((hit.distance - 3.5).abs() < 1e-9);
+        assert!(hit.point.is_near(&Point::new(5.0, 1.5), 1e-9));
+
+        let up = std::f64::consts::FRAC_PI_2;
+        let hit = map.cast_ray(Point::new(5.5, 3.5), -up, 10.0).unwrap();
+        assert_eq!(hit.square, (5, 1));
+        assert!((hit.distance - 1.5).abs() < 1e-9);
+
+        assert!(map.cast_ray(Point::new(1.5, 1.5), 0.0, 3.0).is_none());
+        assert!(map
+            .cast_ray(Point::new(1.5, 1.5), std::f64::consts::PI, 100.0)
+            .is_none());
+        assert_eq!(
+            map.cast_ray(Point::new(5.5, 1.5), 1.0, 1.0)
+                .map(|hit| hit.distance),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn gaussian_blur_spreads_symmetrically_and_keeps_flat_areas() {
+        let mut map = test_map(2, 2);
+        map.set_pixel(8, 8, WHITE);
+        map.gaussian_blur(1.0);
+        let center = pixel(&map, 8, 8)[0];
+        assert!(center < 0xff && center > pixel(&map, 9, 8)[0]);
+        assert_eq!(pixel(&map, 7, 8), pixel(&map, 9, 8));
+        assert_eq!(pixel(&map, 8, 7), pixel(&map, 8, 9));
+        assert_eq!(pixel(&map, 0, 0), [0; 3]);
+
+        let mut flat = test_map(2, 2);
+        flat.pixel_buffer.fill(77);
+        flat.gaussian_blur(2.0);
+        assert!(flat.pixel_buffer.iter().all(|&channel| channel == 77));
+        flat.pixel_buffer[0] = 0;
+        flat.gaussian_blur(0.0);
+        assert_eq!(flat.pixel_buffer[0], 0);
+    }
+
+    #[test]
+    fn gaussian_blur_conserves_brightness() {
+        let mut map = test_map(4, 4);
+        map.fill_circle(
+            Point::new(16.0, 16.0),
+            3.0,
+            WHITE.with_rgb(0xff, 0x80, 0x20),
+        );
+        let energy = |map: &Map| map.pixel_buffer.iter().map(|&c| c as i64).sum::<i64>();
+        let before = energy(&map);
+        map.gaussian_blur(1.5);
+        let after = energy(&map);
+        assert!(
+            (after - before).abs() * 100 <= before,
+            "{before} -> {after}"
+        );
+        assert!(pixel(&map, 16, 16)[0] < 0xff, "the disc was blurred");
+    }
+
+    /// Renders like [`Map::render_stats`] but offers every enabled light to
+    /// every square, as the renderer did before lights were culled.
+    fn unculled_render_stats(map: &mut Map) -> RenderStats {
+        let mut stats = RenderStats::default();
+        map.bake_base();
+        map.pixel_buffer = map.base_buffer.clone().unwrap();
+        let enabled: Vec<usize> = (0..map.lights.len())
+            .filter(|&index| map.lights[index].enabled)
+            .collect();
+        let buckets = vec![enabled; (map.width * map.height) as usize];
+        let width = map.width * 8 * map.sim_scale;
+        for y in 0..map.height * 8 * map.sim_scale {
+            for x in 0..width {
+                let i = ((y * width + x) * 3) as usize;
+                let [r, g, b] = pixel(map, x, y);
+                let base = Color { r, g, b, a: 0xff };
+                let (color, lit) = map.shade_pixel(x, y, base, &buckets, &mut stats);
+                stats.lit_pixels += lit as u64;
+                map.pixel_buffer[i..i + 3].copy_from_slice(&color.channels()[..3]);
+            }
+        }
+        map.apply_exposure();
+        stats
+    }
+
+    #[test]
+    fn sealed_rooms_skip_lights_they_cannot_see() {
+        let mut map = test_map(8, 4);
+        map.fill_border_walls();
+        for row in map.squares.iter_mut() {
+            row[4] = true;
+        }
+        map.add_light(white_light(2.0, 2.0, 8.0));
+        let buckets = map.light_buckets();
+        assert_eq!(buckets[2 * 8 + 2], [0]);
+        assert!(buckets[2 * 8 + 6].is_empty());
+
+        let culled = map.render_stats();
+        let void = map.void_color;
+        assert_eq!(pixel(&map, 6 * 8, 2 * 8), [void.r, void.g, void.b]);
+
+        let rendered = map.pixel_buffer.clone();
+        let unculled = unculled_render_stats(&mut map);
+        assert_eq!(map.pixel_buffer, rendered);
+        assert!(culled.los_checks < unculled.los_checks);
+    }
+
+    #[test]
+    fn dither_is_a_small_fixed_pattern() {
+        let mut map = test_map(1, 1);
+        map.pixel_buffer.fill(128);
+        map.apply_dither();
+        let first = map.pixel_buffer.clone();
+        assert!(first.iter().all(|channel| (126..=130).contains(channel)));
+        assert!(first.iter().any(|&channel| channel != first[0]));
+        // The 4x4 pattern repeats every four pixels across the row.
+        assert_eq!(pixel(&map, 0, 0), pixel(&map, 4, 0));
+        assert_eq!(pixel(&map, 1, 2), pixel(&map, 5, 6));
+
+        map.pixel_buffer.fill(128);
+        map.apply_dither();
+        assert_eq!(map.pixel_buffer, first);
+    }
+
+    #[test]
+    fn dither_breaks_up_bands_in_a_gradient() {
+        let mut map = test_map(8, 1);
+        // A shallow ramp rising one level every eight pixels.
+        let ramp: Vec<Color> = (0..64 * 8)
+            .map(|p| {
+                let level = 100 + (p % 64) as u8 / 8;
+                WHITE.with_rgb(level, level, level)
+            })
+            .collect();
+        map.set_pixel_colors(&ramp).unwrap();
+        let equal_pairs = |map: &Map| {
+            (0..8)
+                .flat_map(|y| (1..64).map(move |x| (x, y)))
+                .filter(|&(x, y)| pixel(map, x - 1, y) == pixel(map, x, y))
+                .count()
+        };
+        let banded = equal_pairs(&map);
+        map.apply_dither();
+        assert!(equal_pairs(&map) < banded);
+    }
+
+    #[test]
+    fn texture_texel_reads_the_rgba_atlas() {
+        let map = test_map(1, 1);
+        let (width, height) = (map.texture_width, map.texture_height);
+        assert_eq!(map.texture.len(), (width * height * 4) as usize);
+        let i = ((3 * width + 5) * 4) as usize;
+        assert_eq!(map.texture_texel(5, 3).channels(), map.texture[i..i + 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn texture_texel_panics_outside_the_atlas() {
+        let map = test_map(1, 1);
+        map.texture_texel(map.texture_width, 0);
+    }
+
+    #[test]
+    fn add_light_clamps_and_try_add_light_rejects_bad_intensity() {
+        let mut map = test_map(4, 4);
+        map.add_light(white_light(1.0, 1.0, -3.0));
+        map.add_light(white_light(1.0, 1.0, f64::NAN));
+        assert!(map.lights().iter().all(|light| light.intensity == 0.0));
+
+        for intensity in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                map.try_add_light(white_light(1.0, 1.0, intensity)),
+                Err(MapError::InvalidLight { .. })
+            ));
+        }
+        map.try_add_light(white_light(1.0, 1.0, 2.0)).unwrap();
+        assert_eq!(map.light_count(), 3);
+    }
+
+    #[test]
+    fn light_defaults_and_disabled_lights() {
+        let light = Light::new(Point::new(1.0, 2.0), WHITE, 3.0);
+        assert!(light.enabled && light.casts_shadows);
+        assert!(light.falloff_gradient.is_none());
+        assert_eq!(light.position, Point::new(1.0, 2.0));
+        let default = Light::default();
+        assert_eq!((default.intensity, default.fov), (1.0, 360.0));
+
+        let mut map = walled_map(4, 4);
+        map.set_light_enabled(0, false);
+        map.render();
+        let mut dark = walled_map(4, 4);
+        dark.lights.clear();
+        dark.render();
+        assert_eq!(map.pixel_buffer, dark.pixel_buffer);
+        assert_eq!(map.light_at(&Point::new(2.0, 2.0)), 0.0);
+    }
+
+    #[test]
+    fn lightmap_holds_only_light() {
+        let map = walled_map(6, 6);
+        let lightmap = map.render_lightmap();
+        assert_eq!(lightmap.len(), 48 * 48 * 4);
+        let at = |x: usize, y: usize| &lightmap[(y * 48 + x) * 4..(y * 48 + x) * 4 + 4];
+        assert_eq!(at(24, 24), [0xff, 0xa0, 0x40, 0xff]);
+        assert_eq!(at(4, 4)[3], 0, "walls are transparent");
+        assert!(at(12, 24)[3] > 0 && at(12, 24)[3] < 0xff);
+    }
+
+    #[test]
+    fn preview_matches_a_unit_scale_render_and_keeps_the_buffer() {
+        let mut map = walled_map(4, 4);
+        map.sim_scale = 2;
+        map.pixel_buffer = vec![0; 64 * 64 * 3];
+        map.color_floor(0.5);
+        map.render();
+        let full = map.pixel_buffer.clone();
+
+        let preview = map.render_preview();
+        assert_eq!(map.pixel_buffer, full);
+        assert_eq!(map.sim_scale, 2);
+
+        let mut unit = walled_map(4, 4);
+        unit.color_floor(0.5);
+        unit.render();
+        assert_eq!(preview, unit.pixel_buffer);
+        map.render();
+        assert_eq!(map.pixel_buffer, full);
+    }
+
+    #[test]
+    fn iter_pixels_walks_the_buffer_row_major() {
+        let mut map = test_map(2, 1);
+        map.set_pixel(3, 5, WHITE);
+        let pixels: Vec<_> = map.iter_pixels().collect();
+        assert_eq!(pixels.len(), 16 * 8);
+        assert_eq!((pixels[17].0, pixels[17].1), (1, 1));
+        let lit: Vec<_> = pixels
+            .iter()
+            .filter(|(_, _, color)| color.r == 0xff)
+            .map(|&(x, y, color)| (x, y, color.a))
+            .collect();
+        assert_eq!(lit, [(3, 5, 0xff)]);
+    }
+
+    #[test]
+    fn volumetric_light_glows_through_shadow() {
+        let mut map = walled_map(8, 8);
+        for y in 1..7 {
+            map.squares[y][5] = true;
+        }
+        map.squares[4][5] = false;
+        map.render();
+        let flat = map.pixel_buffer.clone();
+        let behind_wall = pixel(&map, 6 * 8 + 4, 2 * 8);
+
+        map.set_volumetric(0.5);
+        map.render();
+        assert_ne!(map.pixel_buffer, flat);
+        assert!(pixel(&map, 6 * 8 + 4, 2 * 8)[0] > behind_wall[0]);
+    }
+
+    #[test]
+    fn add_lights_adds_a_batch_with_clamping() {
+        let mut map = test_map(4, 4);
+        map.add_lights((0..3).map(|i| white_light(i as f64, 1.0, i as f64 - 1.0)));
+        assert_eq!(map.light_count(), 3);
+        let intensities: Vec<f64> = map.lights().iter().map(|light| light.intensity).collect();
+        assert_eq!(intensities, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn non_finite_light_positions_are_dropped_or_rejected() {
+        let mut map = test_map(4, 4);
+        map.add_light(white_light(f64::NAN, 1.0, 2.0));
+        map.add_lights([
+            white_light(1.0, f64::INFINITY, 2.0),
+            white_light(1.0, 1.0, 2.0),
+        ]);
+        assert_eq!(map.light_positions(), [Point::new(1.0, 1.0)]);
+        assert!(matches!(
+            map.try_add_light(white_light(f64::NEG_INFINITY, 1.0, 2.0)),
+            Err(MapError::InvalidLight { .. })
+        ));
+        assert_eq!(map.light_count(), 1);
+    }
+
+    #[test]
+    fn light_buckets_keep_every_light_that_reaches_a_square() {
+        let mut map = test_map(10, 8);
+        map.fill_border_walls();
+        map.carve_room(0, 3, 1, 2);
+        for (x, y) in [(4, 1), (4, 2), (4, 3), (6, 5), (7, 5), (2, 6)] {
+            map.squares[y][x] = true;
+        }
+        map.add_light(white_light(2.5, 2.5, 3.0));
+        map.add_light(white_light(7.0, 3.0, 6.0));
+        map.add_light(white_light(-2.0, 4.0, 5.0));
+        map.add_light(white_light(8.5, 6.5, 1.5));
+        map.set_light_enabled(3, false);
+
+        let buckets = map.light_buckets();
+        assert!(buckets.iter().all(|bucket| !bucket.contains(&3)));
+        assert!(buckets
+            .iter()
+            .all(|bucket| bucket.windows(2).all(|w| w[0] < w[1])));
+        assert!(buckets[7 * 10 + 8].is_empty(), "out of every light's reach");
+        for y in 0..64 {
+            for x in 0..80 {
+                let point = map.screen_to_world(x, y);
+                if map.is_within_square(&point) {
+                    continue;
+                }
+                let bucket = &buckets[(y / 8 * 10 + x / 8) as usize];
+                for (index, light) in map.lights().iter().enumerate() {
+                    if map.light_factor(light, &point).is_some() {
+                        assert!(bucket.contains(&index), "light {index} culled at {point:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn light_buckets_render_the_same_with_fewer_evaluations() {
+        let mut map = test_map(16, 8);
+        map.fill_border_walls();
+        map.add_lights(
+            (0..7).map(|i| white_light(1.5 + i as f64 * 2.0, 1.5 + (i % 5) as f64, 1.5)),
+        );
+        let culled = map.render_stats();
+        let rendered = map.pixel_buffer.clone();
+        let unculled = unculled_render_stats(&mut map);
+        assert_eq!(map.pixel_buffer, rendered);
+        assert_eq!(culled.lit_pixels, unculled.lit_pixels);
+        assert!(culled.light_evaluations * 3 < unculled.light_evaluations);
+    }
+
+    #[test]
+    fn render_rgba_keeps_wall_texel_alpha() {
+        let mut map = walled_map(4, 4);
+        let walls = map.color_walls();
+        let rgba = map.render_rgba();
+        for (p, (pixel, rgb)) in rgba.chunks(4).zip(map.pixel_buffer.chunks(3)).enumerate() {
+            assert_eq!(pixel[..3], *rgb);
+            let point = map.screen_to_world(p as u64 % 32, p as u64 / 32);
+            let alpha = if map.is_within_square(&point) {
+                walls[p * 4 + 3]
+            } else {
+                0xff
+            };
+            assert_eq!(pixel[3], alpha);
+        }
+    }
+
+    #[test]
+    fn exposure_rolls_off_highlights() {
+        let mut data = [0, 64, 255];
+        expose(&mut data, 1.0);
+        assert_eq!(data, [0, 57, 161]);
+
+        let mut map = walled_map(4, 4);
+        map.render();
+        let linear = map.pixel_buffer.clone();
+        map.set_exposure(4.0);
+        map.render();
+        for (exposed, linear) in map.pixel_buffer.iter().zip(linear) {
+            assert!(*exposed >= linear || linear > 0xf0);
+        }
+    }
+
+    #[test]
+    fn tile_for_bitmask_copies_the_autotile_from_the_atlas() {
+        let map = test_map(1, 1);
+        for bitmask in [0u8, 0b0100_0010, 0xff] {
+            let tile = map.tile_for_bitmask(bitmask);
+            assert_eq!(tile.len(), 8 * 8 * 4);
+            let (x, y) = map.get_tex_cord(&Point::new(0.0, 0.0), bitmask);
+            assert_eq!(tile[..4], map.texture_texel(x, y).channels());
+            assert_eq!(
+                tile[tile.len() - 4..],
+                map.texture_texel(x + 7, y + 7).channels()
+            );
+        }
+    }
+
+    #[test]
+    fn wrap_edges_joins_opposite_sides() {
+        let mut map = test_map(4, 3);
+        map.squares[0][0] = true;
+        assert!(!map.square_at(4, 3));
+        let edge = Point::new(3.5, 0.5);
+        assert_eq!(map.get_surrounding_square_bitmap(&edge), 0);
+
+        map.set_wrap_edges(true);
+        assert!(map.dirty);
+        assert!(map.square_at(4, 3) && map.square_at(-4, -3));
+        assert!(map.is_within_square(&Point::new(-3.5, 3.5)));
+        assert_eq!(map.get_surrounding_square_bitmap(&edge), 0b0000_1000);
+    }
+
+    #[test]
+    fn shadow_map_marks_visible_open_pixels() {
+        let mut map = walled_map(8, 8);
+        map.squares[4][5] = true;
+        let shadows = map.render_shadow_map(0).unwrap();
+        let at = |x: usize, y: usize| shadows[(y * 64 + x) * 3];
+        assert_eq!(at(3 * 8, 3 * 8), 0xff);
+        assert_eq!(at(4, 4), 0, "walls are black");
+        assert_eq!(at(6 * 8 + 4, 4 * 8 + 4), 0, "behind the inner wall");
+        assert_eq!(at(55, 55), 0xff, "visibility ignores falloff");
+        assert!(matches!(
+            map.render_shadow_map(1),
+            Err(MapError::LightIndexOutOfRange { index: 1, count: 1 })
+        ));
+    }
+
+    #[test]
+    fn border_walls_and_rooms_edit_the_grid() {
+        let mut map = test_map(5, 4);
+        map.fill_border_walls();
+        assert_eq!(map.solid_count(), 5 * 4 - 3 * 2);
+        assert!(map.squares[1][1..4].iter().all(|&solid| !solid));
+
+        map.dirty = false;
+        map.carve_room(3, 2, 10, 10);
+        assert!(map.dirty);
+        assert!(!map.squares[3][4] && !map.squares[2][3]);
+        assert!(map.squares[1][4] && map.squares[3][2]);
+    }
+
+    #[test]
+    fn save_linear_applies_the_srgb_curve() {
+        let mut map = test_map(1, 1);
+        for (i, pixel) in map.pixel_buffer.chunks_mut(3).enumerate() {
+            pixel.copy_from_slice(&[0, 128, 0xff]);
+            pixel[0] = (i % 2) as u8;
+        }
+        let path = temp_path("linear.png");
+        map.save_linear(&path);
+        let (data, width, height) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!((width, height), (8, 8));
+        assert_eq!(data[..8], [0, 188, 0xff, 0xff, 13, 188, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn rebaking_starts_from_a_fresh_floor() {
+        let mut map = walled_map(8, 8);
+        map.color_floor(1.0);
+        map.render();
+        map.carve_room(0, 0, 1, 8);
+        map.render();
+
+        let mut fresh = walled_map(8, 8);
+        fresh.color_floor(1.0);
+        fresh.carve_room(0, 0, 1, 8);
+        fresh.render();
+        assert_eq!(map.diff_count(&fresh).unwrap(), 0);
+    }
+
+    #[test]
+    fn cached_base_is_reused_until_dirty() {
+        let mut map = walled_map(4, 4);
+        assert!(map.render_stats().walls_baked);
+        let first = map.pixel_buffer.clone();
+        let second = map.render_stats();
+        assert!(!second.walls_baked);
+        assert_eq!(map.pixel_buffer, first);
+
+        map.set_tile_size(8, 8);
+        assert!(map.render_stats().walls_baked);
+        map.set_void_color(WHITE);
+        assert!(map.render_stats().walls_baked);
+        assert_ne!(map.pixel_buffer, first);
+    }
+
+    #[test]
+    fn resizing_the_buffer_rebuilds_the_cached_base() {
+        let mut map = walled_map(4, 4);
+        map.color_floor(0.5);
+        map.render();
+        map.sim_scale = 2;
+        map.pixel_buffer = vec![0; 64 * 64 * 3];
+        assert!(map.render_stats().walls_baked);
+        assert_eq!(map.pixel_buffer.len(), 64 * 64 * 3);
+
+        let mut fresh = walled_map(4, 4);
+        fresh.sim_scale = 2;
+        fresh.pixel_buffer = vec![0; 64 * 64 * 3];
+        fresh.color_floor(0.5);
+        fresh.render();
+        assert_eq!(map.pixel_buffer, fresh.pixel_buffer);
+    }
+
+    #[test]
+    fn lights_affecting_lists_visible_lights_in_reach() {
+        let mut map = test_map(8, 4);
+        map.squares[1][4] = true;
+        map.add_light(white_light(1.5, 1.5, 3.0));
+        map.add_light(white_light(6.5, 1.5, 3.0));
+        map.add_light(white_light(3.5, 2.5, 1.0));
+        map.add_light(white_light(3.0, 1.5, 3.0));
+        map.set_light_enabled(3, false);
+        assert_eq!(map.lights_affecting(Point::new(3.5, 1.5)), [0]);
+        assert_eq!(map.lights_affecting(Point::new(3.5, 2.5)), [0, 2]);
+        assert_eq!(map.lights_affecting(Point::new(5.5, 1.5)), [1]);
+        assert!(map.lights_affecting(Point::new(4.0, 3.9)).is_empty());
+    }
+
+    #[test]
+    fn diff_count_and_mask_compare_renders() {
+        let mut a = test_map(2, 2);
+        let mut b = test_map(2, 2);
+        assert_eq!(a.diff_count(&b).unwrap(), 0);
+        b.set_pixel(1, 0, WHITE);
+        b.set_pixel(3, 2, WHITE.with_rgb(0, 1, 0));
+        assert_eq!(a.diff_count(&b).unwrap(), 2);
+        let mask = a.diff_mask(&b).unwrap();
+        assert_eq!(mask[3..6], [0xff, 0, 0]);
+        assert_eq!(mask[..3], [0, 0, 0]);
+        assert_eq!(mask.iter().filter(|&&channel| channel == 0xff).count(), 2);
+
+        a.width = 3;
+        a.pixel_buffer = vec![0; 24 * 16 * 3];
+        assert!(matches!(
+            a.diff_count(&b),
+            Err(MapError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn larger_tiles_scale_the_atlas_lookup() {
+        let mut map = test_map(1, 1);
+        let small: Vec<Vec<u8>> = (0..=255)
+            .map(|bitmask| map.tile_for_bitmask(bitmask))
+            .collect();
+        let (width, height) = (map.texture_width, map.texture_height);
+        map.texture = upscale(&map.texture, width as u64, height as u64, 4, 2);
+        map.texture_width *= 2;
+        map.texture_height *= 2;
+        map.set_tile_size(16, 16);
+        for (bitmask, small) in small.iter().enumerate() {
+            let tile = map.tile_for_bitmask(bitmask as u8);
+            assert_eq!(tile, upscale(small, 8, 8, 4, 2));
+        }
+    }
+
+    #[test]
+    fn quantize_leaves_only_palette_colors() {
+        let mut map = walled_map(4, 4);
+        map.color_floor(0.0);
+        map.render();
+        let palette = [
+            WHITE.with_rgb(0, 0, 0),
+            WHITE,
+            WHITE.with_rgb(0xff, 0x80, 0),
+        ];
+        map.quantize_to_palette(&palette);
+        assert!(map
+            .pixel_buffer
+            .chunks(3)
+            .all(|pixel| palette.iter().any(|color| color.channels()[..3] == *pixel)));
+
+        let before = map.pixel_buffer.clone();
+        map.quantize_to_palette(&[]);
+        assert_eq!(map.pixel_buffer, before);
+    }
+
+    #[test]
+    fn floor_texture_tiles_by_world_position() {
+        let path = temp_path("floor.png");
+        let texels = [0xff, 0, 0, 0, 0, 0xff];
+        try_write_png(&path, 2, 1, png::ColorType::Rgb, &texels).unwrap();
+        let mut map = test_map(1, 1);
+        map.sim_scale = 2;
+        map.pixel_buffer = vec![0; 16 * 16 * 3];
+        map.set_floor_texture(&path).unwrap();
+        map.render();
+        let red = [0xff, 0, 0];
+        let blue = [0, 0, 0xff];
+        assert_eq!(pixel(&map, 0, 0), red);
+        assert_eq!(pixel(&map, 1, 5), red);
+        assert_eq!(pixel(&map, 2, 0), blue);
+        assert_eq!(pixel(&map, 4, 9), red);
+
+        assert!(matches!(
+            map.set_floor_texture("missing.png"),
+            Err(MapError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn upscale_repeats_each_pixel() {
+        let data = [1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            upscale(&data, 2, 1, 3, 2),
+            [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6, 1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]
+        );
+        assert_eq!(upscale(&data, 1, 2, 3, 1), data);
+    }
+
+    #[test]
+    fn tileset_atlas_lays_out_every_bitmask() {
+        let map = test_map(1, 1);
+        let path = temp_path("tileset.png");
+        map.export_tileset_atlas(&path).unwrap();
+        let (data, width, height) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!((width, height), (128, 128));
+        for bitmask in [0u8, 37, 0xff] {
+            let tile = map.tile_for_bitmask(bitmask);
+            let (x, y) = (bitmask as usize % 16 * 8, bitmask as usize / 16 * 8);
+            for row in 0..8 {
+                let start = ((y + row) * 128 + x) * 4;
+                assert_eq!(data[start..start + 32], tile[row * 32..row * 32 + 32]);
+            }
+        }
+    }
+
+    #[test]
+    fn void_color_fills_unpainted_floor() {
+        let mut map = test_map(2, 2);
+        map.render();
+        assert!(map.pixel_buffer.chunks(3).all(|pixel| pixel == [0, 0, 0]));
+        let teal = WHITE.with_rgb(0, 0x80, 0x80);
+        map.set_void_color(teal);
+        map.render();
+        assert!(map
+            .pixel_buffer
+            .chunks(3)
+            .all(|pixel| pixel == [0, 0x80, 0x80]));
+
+        map.color_floor(0.0);
+        map.render();
+        assert_ne!(pixel(&map, 0, 0), [0, 0x80, 0x80]);
+    }
+
+    #[test]
+    fn light_contribution_scales_color_by_falloff() {
+        let map = test_map(4, 4);
+        let mut light = white_light(2.0, 2.0, 2.0);
+        let at_light = map.light_contribution(&light, &Point::new(2.0, 2.0));
+        assert_eq!(at_light.channels(), [0xff; 4]);
+        let halfway = map.light_contribution(&light, &Point::new(3.0, 2.0));
+        assert_eq!(halfway.channels(), [128, 128, 128, 128]);
+        let beyond = map.light_contribution(&light, &Point::new(0.0, 0.0));
+        assert!(beyond.is_transparent());
+        light.enabled = false;
+        assert!(map
+            .light_contribution(&light, &Point::new(2.0, 2.0))
+            .is_transparent());
+    }
+
+    #[test]
+    fn render_matches_light_contribution_over_a_black_floor() {
+        let mut map = walled_map(6, 6);
+        map.squares[2][3] = true;
+        map.render();
+        let light = map.lights[0].clone();
+        for (x, y, color) in map.iter_pixels() {
+            let point = map.screen_to_world(x, y);
+            if map.is_within_square(&point) {
+                continue;
+            }
+            let expected = map.light_contribution(&light, &point).with_alpha(0xff);
+            assert!(
+                color.approx_eq(&expected, 1),
+                "{color:?} vs {expected:?} at {point:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn set_pixel_colors_is_the_inverse_of_iter_pixels() {
+        let mut map = walled_map(2, 2);
+        map.color_floor(3.0);
+        map.render();
+        let colors: Vec<Color> = map.iter_pixels().map(|(_, _, color)| color).collect();
+        let rendered = std::mem::replace(&mut map.pixel_buffer, vec![0; 16 * 16 * 3]);
+        map.set_pixel_colors(&colors).unwrap();
+        assert_eq!(map.pixel_buffer, rendered);
+        assert!(matches!(
+            map.set_pixel_colors(&colors[1..]),
+            Err(MapError::DimensionMismatch {
+                what: "colors",
+                expected: 256,
+                actual: 255,
+            })
+        ));
+    }
+
+    #[test]
+    fn illuminated_fraction_measures_lit_open_floor() {
+        let mut map = test_map(2, 2);
+        assert_eq!(map.illuminated_fraction(0.0), 0.0);
+        map.add_light(white_light(1.0, 1.0, 10.0));
+        assert_eq!(map.illuminated_fraction(0.0), 1.0);
+        map.squares[0][0] = true;
+        map.squares[0][1] = true;
+        assert_eq!(map.illuminated_fraction(0.0), 1.0);
+        let fraction = map.illuminated_fraction(0.95);
+        assert!(fraction > 0.0 && fraction < 1.0);
+        map.fill_border_walls();
+        assert_eq!(map.illuminated_fraction(0.0), 0.0);
+    }
+
+    #[test]
+    fn trace_los_stops_at_the_first_blocking_sample() {
+        let mut map = test_map(4, 2);
+        let clear = map.trace_los(Point::new(0.5, 0.5), Point::new(3.5, 0.5));
+        assert!(!clear.is_empty() && clear.iter().all(|&(_, blocked)| !blocked));
+        assert_eq!(clear[0].0, Point::new(0.5, 0.5));
+
+        map.squares[0][2] = true;
+        let blocked = map.trace_los(Point::new(0.5, 0.5), Point::new(3.5, 0.5));
+        let (last, solid) = *blocked.last().unwrap();
+        assert!(solid && last.x >= 2.0 && last.x < 2.1);
+        assert!(blocked[..blocked.len() - 1]
+            .iter()
+            .all(|&(_, solid)| !solid));
+        assert!(map
+            .trace_los(Point::new(1.0, 1.0), Point::new(1.01, 1.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn zero_sized_maps_render_and_upscale() {
+        assert!(upscale(&[], 0, 4, 3, 2).is_empty());
+        let mut map = test_map(0, 0);
+        map.add_light(white_light(0.0, 0.0, 1.0));
+        map.render();
+        assert!(map.pixel_buffer.is_empty());
+        assert_eq!(map.illuminated_fraction(0.0), 0.0);
+    }
+
+    #[test]
+    fn single_square_and_single_column_maps_render() {
+        let mut single = test_map(1, 1);
+        single.squares[0][0] = true;
+        assert_eq!(
+            single.get_surrounding_square_bitmap(&Point::new(0.5, 0.5)),
+            0
+        );
+        let walls = single.color_walls();
+        assert_eq!(walls.len(), 8 * 8 * 4);
+        assert!(walls.chunks(4).any(|texel| texel[3] > 0));
+        single.add_light(white_light(0.5, 0.5, 1.0));
+        single.render();
+        single.set_wrap_edges(true);
+        assert_eq!(
+            single.get_surrounding_square_bitmap(&Point::new(0.5, 0.5)),
+            0xff
+        );
+        single.render();
+
+        let mut column = test_map(1, 4);
+        for row in column.squares.iter_mut() {
+            row[0] = true;
+        }
+        column.squares[3][0] = false;
+        assert_eq!(
+            column.get_surrounding_square_bitmap(&Point::new(0.5, 1.5)),
+            0b0100_0010
+        );
+        assert_eq!(
+            column.get_surrounding_square_bitmap(&Point::new(0.5, 2.5)),
+            0b0100_0000
+        );
+        assert_eq!(column.color_walls().len(), 8 * 32 * 4);
+        column.add_light(white_light(0.5, 3.5, 2.0));
+        column.render();
+        assert_ne!(pixel(&column, 4, 28), [0; 3]);
+    }
+
+    #[test]
+    fn render_with_mask_marks_walls_in_alpha() {
+        let mut map = walled_map(4, 4);
+        let masked = map.render_with_mask();
+        for (p, (pixel, rgb)) in masked.chunks(4).zip(map.pixel_buffer.chunks(3)).enumerate() {
+            assert_eq!(pixel[..3], *rgb);
+            let point = map.screen_to_world(p as u64 % 32, p as u64 / 32);
+            let wall = map.is_within_square(&point);
+            assert_eq!(pixel[3], if wall { 0xff } else { 0 });
+        }
+    }
+
+    #[test]
+    fn render_region_matches_the_full_render_and_keeps_the_buffer() {
+        let mut map = walled_map(6, 4);
+        map.color_floor(2.0);
+        map.render();
+        let full = map.pixel_buffer.clone();
+        map.carve_room(0, 0, 6, 1);
+        let region = map.render_region(10, 4, 20, 12);
+        assert_eq!(map.pixel_buffer, full, "the last render is left alone");
+        map.render();
+        for (row, y) in region.chunks(20 * 3).zip(4..) {
+            let start = ((y * 48 + 10) * 3) as usize;
+            assert_eq!(row, &map.pixel_buffer[start..start + 20 * 3]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn render_region_rejects_rectangles_past_the_edge() {
+        let mut map = test_map(2, 2);
+        map.render_region(8, 0, 9, 1);
+    }
+
+    #[test]
+    fn max_channel_mixing_never_exceeds_its_sources() {
+        let mut additive = walled_map(4, 4);
+        additive.color_floor(1.0);
+        additive.add_light(Light::new(
+            Point::new(2.5, 2.0),
+            WHITE.with_rgb(0, 0, 0xff),
+            2.0,
+        ));
+        additive.render();
+        let mut max = walled_map(4, 4);
+        max.color_floor(1.0);
+        max.add_light(Light::new(
+            Point::new(2.5, 2.0),
+            WHITE.with_rgb(0, 0, 0xff),
+            2.0,
+        ));
+        max.set_light_mixing(LightMixing::MaxChannel);
+        max.render();
+        assert_ne!(max.pixel_buffer, additive.pixel_buffer);
+
+        let mut floor = walled_map(4, 4);
+        floor.color_floor(1.0);
+        floor.lights.clear();
+        floor.render();
+        for (lit, base) in max.pixel_buffer.chunks(3).zip(floor.pixel_buffer.chunks(3)) {
+            assert!(lit[0] >= base[0] && lit[1] >= base[1] && lit[2] >= base[2]);
+            assert!(lit[1] <= base[1].max(0xa0));
+        }
+    }
+
+    #[test]
+    fn iter_squares_runs_row_major() {
+        let mut map = test_map(3, 2);
+        map.squares[0][2] = true;
+        map.squares[1][0] = true;
+        let squares: Vec<_> = map.iter_squares().collect();
+        assert_eq!(squares.len(), 6);
+        assert_eq!(squares[2], (2, 0, true));
+        assert_eq!(squares[3], (0, 1, true));
+        let solid: Vec<_> = map.solid_squares().collect();
+        assert_eq!(solid, [(2, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn save_view_crops_around_a_point_and_stays_inside() {
+        let mut map = test_map(4, 4);
+        for (i, pixel) in map.pixel_buffer.chunks_mut(3).enumerate() {
+            pixel.copy_from_slice(&[(i % 32) as u8, (i / 32) as u8, 0]);
+        }
+        let path = temp_path("view.png");
+        map.save_view(Point::new(2.0, 2.0), 8, 4, &path).unwrap();
+        let (data, width, height) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!((width, height), (8, 4));
+        assert_eq!(data[..4], [12, 14, 0, 0xff]);
+
+        map.save_view(Point::new(0.0, 4.0), 8, 4, &path).unwrap();
+        let (data, ..) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!(data[..4], [0, 28, 0, 0xff]);
+
+        map.save_view(Point::new(2.0, 2.0), 100, 100, &path)
+            .unwrap();
+        let (_, width, height) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!((width, height), (32, 32));
+    }
+
+    #[test]
+    fn falloff_gradient_shifts_color_with_distance() {
+        let inner = WHITE.with_rgb(0xff, 0xff, 0);
+        let outer = WHITE.with_rgb(0xff, 0, 0);
+        let mut light = white_light(0.0, 0.0, 4.0);
+        assert_eq!(
+            light.color_at(&Point::new(3.0, 0.0)).channels(),
+            WHITE.channels()
+        );
+        light.falloff_gradient = Some((inner, outer));
+        assert_eq!(
+            light.color_at(&Point::new(0.0, 0.0)).channels(),
+            inner.channels()
+        );
+        assert_eq!(
+            light.color_at(&Point::new(2.0, 0.0)).channels(),
+            [0xff, 127, 0, 0xff]
+        );
+        assert_eq!(
+            light.color_at(&Point::new(0.0, 9.0)).channels(),
+            outer.channels()
+        );
+    }
+
+    #[test]
+    fn composite_over_blends_another_render() {
+        let mut base = walled_map(2, 2);
+        base.render();
+        let mut top = test_map(2, 2);
+        top.set_pixel(5, 5, WHITE);
+        base.composite_over(&top, BlendMode::Screen).unwrap();
+        assert_eq!(pixel(&base, 5, 5), [0xff; 3]);
+        base.composite_over(&top, BlendMode::Normal).unwrap();
+        assert_eq!(base.pixel_buffer, top.pixel_buffer);
+
+        let other = test_map(3, 2);
+        let before = base.pixel_buffer.clone();
+        assert!(base.composite_over(&other, BlendMode::Add).is_err());
+        assert_eq!(base.pixel_buffer, before);
+    }
+
+    #[test]
+    fn square_counts_and_enclosed_regions() {
+        let mut map = test_map(6, 5);
+        map.fill_border_walls();
+        assert_eq!((map.solid_count(), map.open_count()), (18, 12));
+        assert!(map.is_region_enclosed((2, 2)));
+        assert!(
+            map.is_region_enclosed((0, 0)),
+            "walls are trivially enclosed"
+        );
+        assert!(!map.is_region_enclosed((6, 0)));
+
+        // A diagonal gap does not let the region out.
+        map.squares[2][5] = false;
+        map.squares[1][4] = true;
+        map.squares[3][4] = true;
+        map.squares[2][4] = true;
+        map.squares[2][5] = false;
+        assert!(map.is_region_enclosed((2, 2)));
+        map.squares[2][4] = false;
+        assert!(!map.is_region_enclosed((2, 2)));
+
+        // Wrapped maps have no edge; an area is open once it loops around.
+        let mut wrapped = test_map(6, 5);
+        wrapped.set_wrap_edges(true);
+        assert!(!wrapped.is_region_enclosed((0, 0)));
+        wrapped.fill_border_walls();
+        assert!(wrapped.is_region_enclosed((2, 2)));
+        for x in 1..5 {
+            wrapped.squares[0][x] = false;
+            wrapped.squares[4][x] = false;
+        }
+        assert!(!wrapped.is_region_enclosed((2, 2)));
+        wrapped.squares[2] = vec![true; 6];
+        assert!(wrapped.is_region_enclosed((2, 1)));
+        assert_eq!(map.solid_count() + map.open_count(), 30);
+    }
+
+    #[test]
+    fn corner_leak_fix_blocks_diagonal_gaps() {
+        let mut map = test_map(3, 3);
+        map.squares[0][1] = true;
+        map.squares[1][0] = true;
+        let (a, b) = (Point::new(0.5, 0.5), Point::new(1.5, 1.5));
+        assert!(map.point_has_los(&a, &b));
+        map.corner_leak_fix = true;
+        assert!(!map.point_has_los(&a, &b));
+        assert!(map.trace_los(a, b).last().unwrap().1);
+        assert!(map.point_has_los(&Point::new(1.5, 1.5), &Point::new(2.5, 2.5)));
+    }
+
+    #[test]
+    fn set_texture_swaps_the_atlas_and_keeps_it_on_error() {
+        let mut map = test_map(2, 2);
+        let gray = [0x40u8; 16 * 8];
+        let path = temp_path("gray_atlas.png");
+        try_write_png(&path, 16, 8, png::ColorType::Grayscale, &gray).unwrap();
+
+        map.dirty = false;
+        map.set_texture(&path).unwrap();
+        assert!(map.dirty);
+        assert_eq!((map.texture_width, map.texture_height), (16, 8));
+        assert_eq!(map.texture_path, path);
+        assert_eq!(map.texture_texel(3, 3).channels(), [0x40, 0x40, 0x40, 0xff]);
+
+        assert!(matches!(
+            map.set_texture("missing.png"),
+            Err(MapError::Io(_))
+        ));
+        assert!(matches!(
+            map.set_texture_bytes(b"not a png"),
+            Err(MapError::Decode(_))
+        ));
+        assert_eq!(map.texture_width, 16);
+
+        map.set_texture_bytes(&std::fs::read("texture-base.png").unwrap())
+            .unwrap();
+        assert!(map.texture_path.is_empty());
+        assert_eq!(map.texture, test_map(1, 1).texture);
+    }
+
+    #[test]
+    fn resizing_the_atlas_drops_the_normal_map() {
+        let mut map = walled_map(4, 4);
+        let (width, height) = (map.texture_width, map.texture_height);
+        map.normal_map = Some([0xff, 128, 0, 0xff].repeat((width * height) as usize));
+        let path = temp_path("double_atlas.png");
+        let double = upscale(&map.texture, width as u64, height as u64, 4, 2);
+        try_write_png(&path, width * 2, height * 2, png::ColorType::Rgba, &double).unwrap();
+
+        map.set_texture(&path).unwrap();
+        map.set_tile_size(16, 16);
+        assert!(map.normal_map.is_none());
+        map.render();
+
+        map.normal_map = Some(vec![0; double.len()]);
+        map.set_texture(&path).unwrap();
+        assert!(map.normal_map.is_some(), "same-sized atlases keep it");
+    }
+
+    #[test]
+    fn try_new_reports_a_missing_atlas() {
+        let map = Map::try_new(2, 2, 1, "missing.png".to_string(), 0.1, 1.0);
+        assert!(matches!(map, Err(MapError::Io(_))));
+    }
+
+    #[test]
+    fn world_and_screen_coordinates_round_trip() {
+        let mut map = test_map(3, 2);
+        map.sim_scale = 3;
+        for y in 0..48 {
+            for x in 0..72 {
+                assert_eq!(map.world_to_screen(map.screen_to_world(x, y)), (x, y));
+            }
+        }
+        assert_eq!(map.screen_to_world(12, 36), Point::new(0.5, 1.5));
+        assert_eq!(map.world_to_screen(Point::new(0.51, 1.49)), (12, 35));
+        assert_eq!(map.world_to_screen(Point::new(-1.0, -0.1)), (0, 0));
+    }
+
+    #[test]
+    fn floor_noise_is_fixed_to_the_world() {
+        let mut unit = test_map(4, 4);
+        unit.color_floor(0.3);
+        let mut double = test_map(4, 4);
+        double.sim_scale = 2;
+        double.pixel_buffer = vec![0; 64 * 64 * 3];
+        double.color_floor(0.3);
+        for (x, y, color) in unit.iter_pixels() {
+            assert_eq!(pixel(&double, x * 2, y * 2), color.channels()[..3]);
+        }
+
+        let before = unit.pixel_buffer.clone();
+        unit.floor_noise_scale = 2.0;
+        unit.color_floor(0.3);
+        assert_ne!(unit.pixel_buffer, before);
+    }
+
+    #[test]
+    fn try_render_checks_sizes_before_rendering() {
+        let mut map = walled_map(4, 4);
+        map.try_render().unwrap();
+        map.sim_scale = 2;
+        assert!(matches!(
+            map.try_render(),
+            Err(MapError::DimensionMismatch {
+                what: "pixel buffer",
+                ..
+            })
+        ));
+        map.pixel_buffer = vec![0; 64 * 64 * 3];
+        map.try_render().unwrap();
+        assert_ne!(pixel(&map, 32, 32), [0; 3]);
+    }
+
+    #[test]
+    fn lights_without_shadows_shine_through_walls() {
+        let mut map = walled_map(8, 8);
+        for y in 1..7 {
+            map.squares[y][5] = true;
+        }
+        let behind = Point::new(6.5, 4.5);
+        assert!(map.lights_affecting(behind).is_empty());
+        map.lights[0].casts_shadows = false;
+        assert_eq!(map.lights_affecting(behind), [0]);
+        let shadows = map.render_shadow_map(0).unwrap();
+        assert_eq!(shadows[(36 * 64 + 52) * 3], 0xff);
+        assert_eq!(shadows[(4 * 64 + 4) * 3], 0, "wall pixels stay black");
+    }
+
+    #[test]
+    fn lerp_with_cross_fades_renders() {
+        let mut from = test_map(1, 1);
+        let mut to = test_map(1, 1);
+        to.pixel_buffer.fill(200);
+        from.lerp_with(&to, 0.0).unwrap();
+        assert!(from.pixel_buffer.iter().all(|&channel| channel == 0));
+        from.lerp_with(&to, 0.5).unwrap();
+        assert!(from.pixel_buffer.iter().all(|&channel| channel == 100));
+        from.lerp_with(&to, 1.0).unwrap();
+        assert_eq!(from.pixel_buffer, to.pixel_buffer);
+        assert!(from.lerp_with(&test_map(2, 1), 0.5).is_err());
+    }
+
+    #[test]
+    fn save_with_writes_the_requested_format() {
+        let mut map = test_map(1, 1);
+        map.set_pixel(0, 0, WHITE.with_rgb(1, 2, 3));
+        let path = temp_path("options.png");
+        let opts = SaveOptions {
+            upscale: 2,
+            rgba: true,
+            ..SaveOptions::default()
+        };
+        map.save_with(&path, opts).unwrap();
+        let (data, width, height) = decode_png_rgba(File::open(&path).unwrap()).unwrap();
+        assert_eq!((width, height), (16, 16));
+        assert_eq!(data[..8], [1, 2, 3, 0xff, 1, 2, 3, 0xff]);
+        assert_eq!(data[16 * 4..16 * 4 + 4], [1, 2, 3, 0xff]);
+
+        let path = temp_path("options.ppm");
+        let opts = SaveOptions {
+            format: ImageFormat::Ppm,
+            rgba: true,
+            ..SaveOptions::default()
+        };
+        map.save_with(&path, opts).unwrap();
+        let ppm = std::fs::read(&path).unwrap();
+        let header = b"P6\n8 8\n255\n";
+        assert_eq!(ppm[..header.len()], *header);
+        assert_eq!(ppm[header.len()..], map.pixel_buffer);
+
+        assert!(map
+            .save_with("missing/dir/out.png", SaveOptions::default())
+            .is_err());
+    }
+
+    #[test]
+    fn light_markers_are_drawn_at_light_positions() {
+        let mut map = test_map(4, 4);
+        map.add_lights([white_light(1.0, 1.0, 1.0), white_light(3.0, 2.5, 1.0)]);
+        assert_eq!(
+            map.light_positions(),
+            [Point::new(1.0, 1.0), Point::new(3.0, 2.5)]
+        );
+        map.draw_light_markers(1.0, WHITE);
+        assert_eq!(pixel(&map, 7, 7), [0xff; 3]);
+        assert_eq!(pixel(&map, 8, 8), [0xff; 3]);
+        assert_eq!(pixel(&map, 9, 8), [0; 3]);
+        assert_eq!(pixel(&map, 23, 19), [0xff; 3]);
+        assert_eq!(pixel(&map, 24, 20), [0xff; 3]);
+        assert_eq!(pixel(&map, 10, 10), [0; 3]);
+    }
+
+    #[test]
+    fn light_exposure_rolls_off_summed_light() {
+        let mut map = test_map(4, 4);
+        map.add_light(white_light(2.0, 2.0, 2.0));
+        map.set_light_exposure(0.0);
+        map.render();
+        assert_eq!(pixel(&map, 16, 16), [161; 3]);
+        map.set_light_exposure(1.0);
+        map.render();
+        assert_eq!(pixel(&map, 16, 16), [220; 3]);
+
+        // Stacking the same light keeps its hue instead of clipping.
+        let red = WHITE.with_rgb(0xc0, 0x20, 0x20);
+        map.lights.clear();
+        map.add_lights((0..4).map(|_| Light::new(Point::new(2.0, 2.0), red, 2.0)));
+        map.render();
+        let [r, g, b] = pixel(&map, 16, 16);
+        assert!(r > g && g == b && r < 0xc0);
+    }
+
+    #[test]
+    fn vignette_darkens_toward_the_corners() {
+        let mut map = test_map(4, 4);
+        map.pixel_buffer.fill(200);
+        map.apply_vignette(0.5);
+        assert!(pixel(&map, 16, 16)[0] >= 197);
+        let corner = pixel(&map, 0, 0)[0];
+        assert!((100..110).contains(&corner));
+        assert_eq!(pixel(&map, 31, 31), pixel(&map, 0, 0));
+        assert!(pixel(&map, 8, 16)[0] > corner);
+
+        for (i, channel) in map.pixel_buffer.iter_mut().enumerate() {
+            *channel = i as u8;
+        }
+        let before = map.pixel_buffer.clone();
+        map.apply_vignette(0.0);
+        assert_eq!(map.pixel_buffer, before, "zero strength is a no-op");
+    }
+
+    #[test]
+    fn for_each_world_pixel_passes_world_positions() {
+        let mut map = test_map(2, 2);
+        map.sim_scale = 2;
+        map.pixel_buffer = vec![0; 32 * 32 * 3];
+        let mut visited = 0;
+        map.for_each_world_pixel(|point, color| {
+            visited += 1;
+            if point.x >= 1.0 && point.y < 0.5 {
+                *color = WHITE.with_alpha(0);
+            }
+        });
+        assert_eq!(visited, 32 * 32);
+        assert_eq!(pixel(&map, 16, 0), [0xff; 3]);
+        assert_eq!(pixel(&map, 31, 7), [0xff; 3]);
+        assert_eq!(pixel(&map, 15, 0), [0; 3]);
+        assert_eq!(pixel(&map, 16, 8), [0; 3]);
     }
 }
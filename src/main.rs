@@ -25,8 +25,8 @@ fn main() {
             a: 255,
         },
         intensity: 15.0,
-        angle: 0.0,
         fov: 90.0,
+        ..Default::default()
     });
 
     // Render the scene with ray tracing
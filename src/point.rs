@@ -1,7 +1,47 @@
 use std::ops::*;
 
+/// A curve that remaps interpolation progress for smoother motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Starts slow and speeds up.
+    EaseInQuad,
+    /// Starts fast and slows down.
+    EaseOutQuad,
+    /// Slow at both ends and fastest in the middle.
+    EaseInOutCubic,
+}
+
+impl Easing {
+    /// Remap progress through the easing curve.
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - The progress, from 0.0 to 1.0.
+    ///
+    /// # Returns
+    ///
+    /// The eased progress. Every curve maps 0.0 to 0.0 and 1.0 to 1.0.
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInQuad => t * t,
+            Easing::EaseOutQuad => t * (2.0 - t),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
 /// A struct representing a point in 2D space.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     /// The x-coordinate of the point.
     pub x: f64,
@@ -134,6 +174,18 @@ impl PartialEq for Point {
     }
 }
 
+impl From<[f64; 2]> for Point {
+    fn from(a: [f64; 2]) -> Point {
+        Point::from_array(a)
+    }
+}
+
+impl From<(f64, f64)> for Point {
+    fn from((x, y): (f64, f64)) -> Point {
+        Point { x, y }
+    }
+}
+
 impl Point {
     /// Creates a new `Point` with the given x and y coordinates.
     ///
@@ -149,6 +201,30 @@ impl Point {
         Point { x, y }
     }
 
+    /// Creates a new `Point`, rejecting NaN or infinite coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The x-coordinate of the point.
+    /// * `y` - The y-coordinate of the point.
+    ///
+    /// # Returns
+    ///
+    /// A new `Point`, or `None` if either coordinate is not finite.
+    pub fn new_checked(x: f64, y: f64) -> Option<Point> {
+        let point = Point { x, y };
+        point.is_finite().then_some(point)
+    }
+
+    /// Checks that both coordinates are finite (neither NaN nor infinite).
+    ///
+    /// # Returns
+    ///
+    /// `true` if both coordinates are finite, otherwise `false`.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
     /// Calculates the distance between this point and another point.
     ///
     /// # Arguments
@@ -215,6 +291,25 @@ impl Point {
         }
     }
 
+    /// Calculates the centroid (arithmetic mean) of a set of points.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The points to average.
+    ///
+    /// # Returns
+    ///
+    /// The centroid of the points, or `None` if `points` is empty.
+    pub fn centroid(points: &[Point]) -> Option<Point> {
+        if points.is_empty() {
+            return None;
+        }
+        let sum = points
+            .iter()
+            .fold(Point::new(0.0, 0.0), |sum, &point| sum + point);
+        Some(sum / points.len() as f64)
+    }
+
     /// Checks if this point is near another point within a specified tolerance.
     ///
     /// # Arguments
@@ -228,4 +323,500 @@ impl Point {
     pub fn is_near(&self, other: &Point, tolerance: f64) -> bool {
         self.distance(other) <= tolerance
     }
+
+    /// Move toward another point along an easing curve.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The point to move toward.
+    /// * `t` - The progress, where 0.0 returns `self` and 1.0 returns `target`.
+    /// * `easing` - The curve `t` is remapped through before interpolating.
+    ///
+    /// # Returns
+    ///
+    /// A new `Point` between this point and `target`.
+    pub fn ease(&self, target: &Point, t: f64, easing: Easing) -> Point {
+        *self + (*target - *self) * easing.apply(t)
+    }
+
+    /// Generates evenly spaced points along the segment from this point to another.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The end point of the segment.
+    /// * `steps` - The number of points to generate, including both endpoints.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` of `steps` points. A `steps` of 0 yields an empty `Vec`, and a
+    /// `steps` of 1 yields only this point.
+    pub fn lerp_path(&self, other: &Point, steps: usize) -> Vec<Point> {
+        match steps {
+            0 => Vec::new(),
+            1 => vec![*self],
+            _ => (0..steps)
+                .map(|i| {
+                    let t = i as f64 / (steps - 1) as f64;
+                    *self + (*other - *self) * t
+                })
+                .collect(),
+        }
+    }
+
+    /// Calculates the unit vector pointing from this point toward another point.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The point to face.
+    ///
+    /// # Returns
+    ///
+    /// A normalized direction vector, or the zero vector if the points coincide.
+    pub fn direction_to(&self, other: &Point) -> Point {
+        (*other - *self).normalize()
+    }
+
+    /// Converts this point to a flat `[x, y]` array.
+    ///
+    /// # Returns
+    ///
+    /// An array containing the x and y coordinates.
+    pub fn to_array(&self) -> [f64; 2] {
+        [self.x, self.y]
+    }
+
+    /// Creates a point from a flat `[x, y]` array.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - An array containing the x and y coordinates.
+    ///
+    /// # Returns
+    ///
+    /// A new `Point` instance.
+    pub fn from_array(a: [f64; 2]) -> Point {
+        Point { x: a[0], y: a[1] }
+    }
+
+    /// Snaps each coordinate to the nearest multiple of `cell`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The grid spacing.
+    ///
+    /// # Returns
+    ///
+    /// A new `Point` on the nearest grid intersection.
+    pub fn snap_to_grid(&self, cell: f64) -> Point {
+        Point {
+            x: (self.x / cell).round() * cell,
+            y: (self.y / cell).round() * cell,
+        }
+    }
+
+    /// Snaps this point to the center of the grid cell containing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The grid spacing.
+    ///
+    /// # Returns
+    ///
+    /// A new `Point` at `cell * (n + 0.5)` on each axis.
+    pub fn snap_to_cell_center(&self, cell: f64) -> Point {
+        Point {
+            x: ((self.x / cell).floor() + 0.5) * cell,
+            y: ((self.y / cell).floor() + 0.5) * cell,
+        }
+    }
+
+    /// Scales this vector down so its magnitude does not exceed `max`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The maximum allowed magnitude.
+    ///
+    /// # Returns
+    ///
+    /// A vector with the same direction and a magnitude of at most `max`.
+    /// Vectors already within the limit, including the zero vector, are
+    /// returned unchanged.
+    pub fn clamp_magnitude(&self, max: f64) -> Point {
+        let mag = self.magnitude();
+        if mag > max {
+            *self * (max / mag)
+        } else {
+            *self
+        }
+    }
+
+    /// Moves this point a given distance along a direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction to move in; it does not need to be normalized.
+    /// * `distance` - How far to move.
+    ///
+    /// # Returns
+    ///
+    /// The offset point, or this point unchanged if `direction` is the zero vector.
+    pub fn offset_by(&self, direction: &Point, distance: f64) -> Point {
+        *self + direction.normalize() * distance
+    }
+
+    /// Rotates this vector about the origin.
+    ///
+    /// # Arguments
+    ///
+    /// * `radians` - The angle to rotate by. Positive angles turn the same way as
+    ///   [`Point::rotate_ccw90`].
+    ///
+    /// # Returns
+    ///
+    /// The rotated vector, with the same magnitude.
+    pub fn rotate(&self, radians: f64) -> Point {
+        let (sin, cos) = radians.sin_cos();
+        Point {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        }
+    }
+
+    /// Rotates this vector about the origin by an angle in degrees.
+    ///
+    /// # Arguments
+    ///
+    /// * `degrees` - The angle to rotate by, converted to radians for [`Point::rotate`].
+    ///
+    /// # Returns
+    ///
+    /// The rotated vector, with the same magnitude.
+    pub fn rotate_degrees(&self, degrees: f64) -> Point {
+        self.rotate(degrees.to_radians())
+    }
+
+    /// Rotates this vector 90 degrees clockwise without trigonometry.
+    ///
+    /// # Returns
+    ///
+    /// The exact rotated vector `(y, -x)`.
+    pub fn rotate_cw90(&self) -> Point {
+        Point {
+            x: self.y,
+            y: -self.x,
+        }
+    }
+
+    /// Rotates this vector 90 degrees counter-clockwise without trigonometry.
+    ///
+    /// # Returns
+    ///
+    /// The exact rotated vector `(-y, x)`.
+    pub fn rotate_ccw90(&self) -> Point {
+        Point {
+            x: -self.y,
+            y: self.x,
+        }
+    }
+
+    /// Checks whether this point lies inside a triangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `a`, `b`, `c` - The triangle's vertices, in either winding order.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the point is inside the triangle or on one of its edges,
+    /// otherwise `false`. A triangle with zero area contains no points.
+    pub fn in_triangle(&self, a: &Point, b: &Point, c: &Point) -> bool {
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if area == 0.0 {
+            return false;
+        }
+        let cross =
+            |p: &Point, q: &Point| (q.x - p.x) * (self.y - p.y) - (q.y - p.y) * (self.x - p.x);
+        let d1 = cross(a, b);
+        let d2 = cross(b, c);
+        let d3 = cross(c, a);
+        let has_negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+        let has_positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+        !(has_negative && has_positive)
+    }
+
+    /// Checks whether this point lies inside a polygon using the even-odd rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The polygon's vertices in order. The last vertex connects back to the first,
+    ///   and the polygon may be concave.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the point is inside the polygon or exactly on one of its
+    /// edges, otherwise `false`. Polygons with fewer than 3 vertices contain
+    /// no points.
+    pub fn in_polygon(&self, vertices: &[Point]) -> bool {
+        if vertices.len() < 3 {
+            return false;
+        }
+        let mut inside = false;
+        let mut previous = vertices[vertices.len() - 1];
+        for &current in vertices {
+            let cross = (current.x - previous.x) * (self.y - previous.y)
+                - (current.y - previous.y) * (self.x - previous.x);
+            let within_x =
+                self.x >= previous.x.min(current.x) && self.x <= previous.x.max(current.x);
+            let within_y =
+                self.y >= previous.y.min(current.y) && self.y <= previous.y.max(current.y);
+            if cross == 0.0 && within_x && within_y {
+                return true;
+            }
+            if (current.y > self.y) != (previous.y > self.y) {
+                let crossing_x = previous.x
+                    + (self.y - previous.y) * (current.x - previous.x) / (current.y - previous.y);
+                if self.x < crossing_x {
+                    inside = !inside;
+                }
+            }
+            previous = current;
+        }
+        inside
+    }
+
+    /// Get a key for sorting points deterministically.
+    ///
+    /// # Returns
+    ///
+    /// A tuple ordering points by x, then y, following the IEEE 754 total
+    /// order: -0.0 sorts before 0.0 and NaNs sort at the ends by sign.
+    pub fn sort_key(&self) -> (u64, u64) {
+        (total_order_bits(self.x), total_order_bits(self.y))
+    }
+}
+
+/// Maps an `f64` to a `u64` whose unsigned order matches `f64::total_cmp`.
+fn total_order_bits(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_path_includes_both_endpoints() {
+        let start = Point::new(0.0, 0.0);
+        let end = Point::new(4.0, 2.0);
+        assert_eq!(start.lerp_path(&end, 3), [start, Point::new(2.0, 1.0), end]);
+        assert_eq!(start.lerp_path(&end, 1), [start]);
+        assert!(start.lerp_path(&end, 0).is_empty());
+    }
+
+    #[test]
+    fn direction_to_is_a_unit_vector() {
+        let from = Point::new(1.0, 1.0);
+        assert_eq!(
+            from.direction_to(&Point::new(1.0, 5.0)),
+            Point::new(0.0, 1.0)
+        );
+        let diagonal = from.direction_to(&Point::new(-2.0, -3.0));
+        assert!((diagonal.magnitude() - 1.0).abs() < 1e-12);
+        assert!(diagonal.x < 0.0 && diagonal.y < 0.0);
+        assert_eq!(from.direction_to(&from), Point::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn array_and_tuple_conversions_round_trip() {
+        let point = Point::new(1.5, -2.0);
+        assert_eq!(point.to_array(), [1.5, -2.0]);
+        assert_eq!(Point::from_array(point.to_array()), point);
+        assert_eq!(Point::from([1.5, -2.0]), point);
+        assert_eq!(Point::from((1.5, -2.0)), point);
+    }
+
+    #[test]
+    fn centroid_averages_the_points() {
+        let points = [
+            Point::new(0.0, 0.0),
+            Point::new(4.0, 0.0),
+            Point::new(4.0, 4.0),
+            Point::new(0.0, 4.0),
+        ];
+        assert_eq!(Point::centroid(&points), Some(Point::new(2.0, 2.0)));
+        assert_eq!(Point::centroid(&points[1..2]), Some(points[1]));
+        assert_eq!(Point::centroid(&[]), None);
+    }
+
+    #[test]
+    fn snapping_rounds_to_grid_lines_or_cell_centers() {
+        let point = Point::new(1.2, -0.7);
+        assert_eq!(point.snap_to_grid(1.0), Point::new(1.0, -1.0));
+        assert_eq!(point.snap_to_grid(0.5), Point::new(1.0, -0.5));
+        assert_eq!(point.snap_to_cell_center(1.0), Point::new(1.5, -0.5));
+        assert_eq!(point.snap_to_cell_center(2.0), Point::new(1.0, -1.0));
+    }
+
+    #[test]
+    fn new_checked_rejects_non_finite_coordinates() {
+        assert_eq!(Point::new_checked(1.0, 2.0), Some(Point::new(1.0, 2.0)));
+        assert_eq!(Point::new_checked(f64::NAN, 2.0), None);
+        assert_eq!(Point::new_checked(1.0, f64::INFINITY), None);
+        assert!(!Point::new(f64::NEG_INFINITY, 0.0).is_finite());
+        assert!(Point::new(-1e300, 1e300).is_finite());
+    }
+
+    #[test]
+    fn clamp_magnitude_only_shortens_long_vectors() {
+        assert_eq!(
+            Point::new(3.0, 4.0).clamp_magnitude(2.5),
+            Point::new(1.5, 2.0)
+        );
+        assert_eq!(
+            Point::new(3.0, 4.0).clamp_magnitude(10.0),
+            Point::new(3.0, 4.0)
+        );
+        assert_eq!(
+            Point::new(0.0, 0.0).clamp_magnitude(0.0),
+            Point::new(0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn offset_by_moves_along_the_normalized_direction() {
+        let point = Point::new(1.0, 1.0);
+        assert_eq!(
+            point.offset_by(&Point::new(0.0, -10.0), 0.5),
+            Point::new(1.0, 0.5)
+        );
+        assert_eq!(point.offset_by(&Point::new(0.0, 0.0), 0.5), point);
+    }
+
+    #[test]
+    fn quarter_turns_are_exact_and_inverse() {
+        let point = Point::new(3.0, -2.0);
+        assert_eq!(point.rotate_cw90(), Point::new(-2.0, -3.0));
+        assert_eq!(point.rotate_ccw90(), Point::new(2.0, 3.0));
+        assert_eq!(point.rotate_cw90().rotate_ccw90(), point);
+        let full_turn = point
+            .rotate_cw90()
+            .rotate_cw90()
+            .rotate_cw90()
+            .rotate_cw90();
+        assert_eq!(full_turn, point);
+    }
+
+    #[test]
+    fn in_triangle_includes_edges_in_either_winding() {
+        let (a, b, c) = (
+            Point::new(0.0, 0.0),
+            Point::new(4.0, 0.0),
+            Point::new(0.0, 4.0),
+        );
+        assert!(Point::new(1.0, 1.0).in_triangle(&a, &b, &c));
+        assert!(Point::new(1.0, 1.0).in_triangle(&c, &b, &a));
+        assert!(Point::new(2.0, 2.0).in_triangle(&a, &b, &c));
+        assert!(a.in_triangle(&a, &b, &c));
+        assert!(!Point::new(3.0, 3.0).in_triangle(&a, &b, &c));
+        assert!(!Point::new(-0.1, 1.0).in_triangle(&a, &b, &c));
+    }
+
+    #[test]
+    fn degenerate_triangles_contain_nothing() {
+        let origin = Point::new(0.0, 0.0);
+        assert!(!Point::new(100.0, 100.0).in_triangle(&origin, &origin, &origin));
+        assert!(!origin.in_triangle(&origin, &origin, &origin));
+        let (a, b, c) = (
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 0.0),
+            Point::new(2.0, 0.0),
+        );
+        assert!(!Point::new(5.0, 0.0).in_triangle(&a, &b, &c));
+        assert!(!Point::new(1.5, 0.0).in_triangle(&a, &b, &c));
+    }
+
+    #[test]
+    fn easing_curves_share_endpoints() {
+        let curves = [
+            Easing::Linear,
+            Easing::EaseInQuad,
+            Easing::EaseOutQuad,
+            Easing::EaseInOutCubic,
+        ];
+        for easing in curves {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+        assert_eq!(Easing::EaseInQuad.apply(0.5), 0.25);
+        assert_eq!(Easing::EaseOutQuad.apply(0.5), 0.75);
+        assert_eq!(Easing::EaseInOutCubic.apply(0.5), 0.5);
+
+        let start = Point::new(0.0, 0.0);
+        let end = Point::new(8.0, 4.0);
+        assert_eq!(start.ease(&end, 0.5, Easing::Linear), Point::new(4.0, 2.0));
+        assert_eq!(
+            start.ease(&end, 0.5, Easing::EaseInQuad),
+            Point::new(2.0, 1.0)
+        );
+        assert_eq!(start.ease(&end, 1.0, Easing::EaseOutQuad), end);
+    }
+
+    #[test]
+    fn sort_key_follows_total_order() {
+        let mut points = [
+            Point::new(1.0, 0.0),
+            Point::new(0.0, 1.0),
+            Point::new(0.0, -0.0),
+            Point::new(-0.0, 5.0),
+            Point::new(-1.0, 0.0),
+            Point::new(f64::NAN, 0.0),
+        ];
+        points.sort_by_key(Point::sort_key);
+        let xs: Vec<_> = points.iter().map(|point| point.to_array()).collect();
+        assert_eq!(xs[0], [-1.0, 0.0]);
+        assert!(xs[1][0].is_sign_negative() && xs[1][0] == 0.0);
+        assert_eq!(xs[2][1], -0.0);
+        assert!(xs[2][1].is_sign_negative());
+        assert_eq!(xs[3], [0.0, 1.0]);
+        assert_eq!(xs[4], [1.0, 0.0]);
+        assert!(xs[5][0].is_nan());
+    }
+
+    #[test]
+    fn in_polygon_handles_concave_shapes_and_edges() {
+        // An L shape with the top-right quadrant cut away.
+        let shape = [
+            Point::new(0.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 1.0),
+            Point::new(1.0, 1.0),
+            Point::new(1.0, 2.0),
+            Point::new(0.0, 2.0),
+        ];
+        assert!(Point::new(0.5, 0.5).in_polygon(&shape));
+        assert!(Point::new(1.5, 0.5).in_polygon(&shape));
+        assert!(Point::new(0.5, 1.5).in_polygon(&shape));
+        assert!(!Point::new(1.5, 1.5).in_polygon(&shape));
+        assert!(Point::new(1.0, 1.5).in_polygon(&shape));
+        assert!(!Point::new(3.0, 0.5).in_polygon(&shape));
+        assert!(!Point::new(0.5, 0.5).in_polygon(&shape[..2]));
+    }
+
+    #[test]
+    fn rotate_matches_the_quarter_turns() {
+        let point = Point::new(3.0, -2.0);
+        assert!(point
+            .rotate(std::f64::consts::FRAC_PI_2)
+            .is_near(&point.rotate_ccw90(), 1e-12));
+        assert!(point
+            .rotate_degrees(-90.0)
+            .is_near(&point.rotate_cw90(), 1e-12));
+        assert!((point.rotate_degrees(33.0).magnitude() - point.magnitude()).abs() < 1e-12);
+        assert!(point.rotate_degrees(360.0).is_near(&point, 1e-12));
+    }
 }
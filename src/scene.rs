@@ -0,0 +1,189 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Color, Light, LightMixing, Map, MapError};
+
+/// On-disk form of a `Map`. The texture atlas is referenced by path rather than
+/// inlined, and the rendered pixel buffer is not stored.
+#[derive(Serialize, Deserialize)]
+struct Scene {
+    height: u64,
+    width: u64,
+    sim_scale: u64,
+    texture_path: String,
+    tile_width: u32,
+    tile_height: u32,
+    cast_step_size: f64,
+    rays_per_degree: f64,
+    shadow_bias: f64,
+    corner_leak_fix: bool,
+    light_walls: bool,
+    volumetric_density: f64,
+    floor_noise_scale: f64,
+    exposure: Option<f64>,
+    wrap_edges: bool,
+    light_mixing: LightMixing,
+    light_exposure: Option<f64>,
+    void_color: Color,
+    active_layer: usize,
+    layers: Vec<Vec<Vec<bool>>>,
+    lights: Vec<Light>,
+}
+
+impl Map {
+    /// Saves the geometry, lights and settings of the map as a JSON scene file.
+    pub fn save_scene(&self, path: &str) -> Result<(), MapError> {
+        let mut layers = self.layers.clone();
+        layers[self.active_layer] = self.squares.clone();
+        let scene = Scene {
+            height: self.height,
+            width: self.width,
+            sim_scale: self.sim_scale,
+            texture_path: self.texture_path.clone(),
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            cast_step_size: self.cast_step_size,
+            rays_per_degree: self.rays_per_degree,
+            shadow_bias: self.shadow_bias,
+            corner_leak_fix: self.corner_leak_fix,
+            light_walls: self.light_walls,
+            volumetric_density: self.volumetric_density,
+            floor_noise_scale: self.floor_noise_scale,
+            exposure: self.exposure,
+            wrap_edges: self.wrap_edges,
+            light_mixing: self.light_mixing,
+            light_exposure: self.light_exposure,
+            void_color: self.void_color,
+            active_layer: self.active_layer,
+            layers,
+            lights: self.lights.clone(),
+        };
+        fs::write(path, serde_json::to_string(&scene)?)?;
+        Ok(())
+    }
+
+    /// Loads a map from a JSON scene file written by [`Map::save_scene`],
+    /// reloading the texture atlas from its stored path. Lights outside the
+    /// grid are kept, as [`Map::add_light`] accepts them.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::Io` or `MapError::Decode` if the atlas is missing or
+    /// unreadable, including scenes saved with an empty `texture_path` after
+    /// [`Map::set_texture_bytes`].
+    pub fn load_scene(path: &str) -> Result<Map, MapError> {
+        let scene: Scene = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let mut map = Map::try_new(
+            scene.height,
+            scene.width,
+            scene.sim_scale,
+            scene.texture_path,
+            scene.cast_step_size,
+            scene.rays_per_degree,
+        )?;
+        map.tile_width = scene.tile_width;
+        map.tile_height = scene.tile_height;
+        map.shadow_bias = scene.shadow_bias;
+        map.corner_leak_fix = scene.corner_leak_fix;
+        map.light_walls = scene.light_walls;
+        map.volumetric_density = scene.volumetric_density;
+        map.floor_noise_scale = scene.floor_noise_scale;
+        map.exposure = scene.exposure;
+        map.wrap_edges = scene.wrap_edges;
+        map.light_mixing = scene.light_mixing;
+        map.light_exposure = scene.light_exposure;
+        map.void_color = scene.void_color;
+        map.lights = scene.lights;
+        map.squares =
+            scene
+                .layers
+                .get(scene.active_layer)
+                .cloned()
+                .ok_or(MapError::DimensionMismatch {
+                    what: "layers",
+                    expected: scene.active_layer + 1,
+                    actual: scene.layers.len(),
+                })?;
+        map.layers = scene.layers;
+        map.active_layer = scene.active_layer;
+        map.check_dimensions()?;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("shader_test_{name}"))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn scenes_round_trip_through_json() {
+        let mut map = Map::new(6, 5, 1, "texture-base.png".to_string(), 0.1, 1.0);
+        map.fill_border_walls();
+        map.add_light(Light::new(
+            Point::new(2.5, 2.5),
+            Color::from_rgba((0xff, 0xa0, 0x40, 0xff)),
+            3.0,
+        ));
+        map.set_void_color(Color::from_rgba((0x10, 0x20, 0x30, 0xff)));
+        map.corner_leak_fix = true;
+        let path = temp_path("round_trip.json");
+        map.save_scene(&path).unwrap();
+
+        let mut loaded = Map::load_scene(&path).unwrap();
+        assert_eq!(loaded.squares, map.squares);
+        assert_eq!(loaded.light_positions(), map.light_positions());
+        assert!(loaded.corner_leak_fix);
+        map.render();
+        loaded.render();
+        assert_eq!(loaded.pixel_buffer, map.pixel_buffer);
+    }
+
+    #[test]
+    fn scenes_keep_lights_outside_the_map() {
+        let mut map = Map::new(2, 2, 1, "texture-base.png".to_string(), 0.1, 1.0);
+        map.add_light(Light::new(
+            Point::new(-3.0, 1.0),
+            Light::default().color,
+            5.0,
+        ));
+        let path = temp_path("off_map_light.json");
+        map.save_scene(&path).unwrap();
+        let loaded = Map::load_scene(&path).unwrap();
+        assert_eq!(loaded.light_positions(), [Point::new(-3.0, 1.0)]);
+    }
+
+    #[test]
+    fn load_scene_reports_a_missing_atlas() {
+        let mut map = Map::new(2, 2, 1, "texture-base.png".to_string(), 0.1, 1.0);
+        map.set_texture_bytes(&fs::read("texture-base.png").unwrap())
+            .unwrap();
+        let path = temp_path("bytes_atlas.json");
+        map.save_scene(&path).unwrap();
+        assert!(matches!(Map::load_scene(&path), Err(MapError::Io(_))));
+        assert!(matches!(
+            Map::load_scene("missing.json"),
+            Err(MapError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn missing_light_fields_take_their_defaults() {
+        let light: Light =
+            serde_json::from_str(r#"{"position": {"x": 1.0, "y": 2.0}, "intensity": 4.0}"#)
+                .unwrap();
+        assert_eq!(light.position, Point::new(1.0, 2.0));
+        assert_eq!(light.intensity, 4.0);
+        assert_eq!(light.fov, 360.0);
+        assert!(light.enabled && light.casts_shadows);
+        assert_eq!(light.color.to_rgba(), Light::default().color.to_rgba());
+    }
+}